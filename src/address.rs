@@ -0,0 +1,186 @@
+use std::sync::LazyLock;
+use regex::Regex;
+use serde::Serialize;
+use crate::countries;
+
+/// A part that is only a postal code, e.g. `90018`, `1012 LG`, `SW1A 2AA`, `00-950` or `A-1010`.
+static POSTAL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4,5}(-\d{4})?|\d{4}\s?[A-Z]{2}|\d{3}\s\d{2}|\d{2}-\d{3}|\d{4}-\d{3}|[A-Z]{1,2}-\d{4,5}|[A-Z]{1,2}\d[A-Z\d]?\s?\d[A-Z]{2}|[A-Z]\d[A-Z]\s?\d[A-Z]\d)$")
+        .expect("static regex is valid")
+});
+/// Postal code in front of the city, e.g. `10115 Berlin`, `A-1010 Wien` or `1012 LG Amsterdam`.
+static POSTAL_CITY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4}\s?[A-Z]{2}|\d{4,5}|[A-Z]{1,2}-\d{4,5})\s+(\D.*)$").expect("static regex is valid")
+});
+/// Postal code after the city, optionally with a state or province in between, e.g. `London SW1A 2AA`,
+/// `Toronto ON M5V 2T6` or `Wilmington DE 19801`.
+static CITY_POSTAL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\D+?)\s+(?:([A-Z]{2})\s+)?([A-Z]{1,2}\d[A-Z\d]?\s?\d[A-Z]{2}|[A-Z]\d[A-Z]\s?\d[A-Z]\d|\d{5}(?:-\d{4})?)$")
+        .expect("static regex is valid")
+});
+/// A region in brackets after the city, e.g. `Los Angeles (CA)`.
+static CITY_REGION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(.*?)\s*\(([^)]+)\)$").expect("static regex is valid"));
+/// A trailing part like `CA`, a state or province unless it follows a postal code and city.
+static REGION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Z]{2}$").expect("static regex is valid"));
+
+/// The parts of a `Rechnungsadresse`, every part that could not be recognized is `None`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BillingAddress {
+    pub name: Option<String>,
+    pub street: Option<String>,
+    pub postal_code: Option<String>,
+    pub city: Option<String>,
+    /// state or province, e.g. `CA`
+    pub region: Option<String>,
+    /// the country as written in the address
+    pub country: Option<String>,
+    /// ISO 3166-1 alpha-2 code of `country`
+    pub country_code: Option<&'static str>,
+}
+
+/// Splits a comma separated address as found in the exports, e.g.
+/// `GOAT, 3433 W Exposition Place, 90018, Los Angeles (CA), USA` or `Shop GmbH, Hauptstr. 1, 10115 Berlin, Deutschland`.
+/// The postal code is either a part of its own followed by the city, or written in front of or after the city.
+/// A postal code part only counts as such if the city follows it, so `APT 5B` or `12` stay part of the street.
+/// A trailing two letter code only counts as country directly after postal code and city, e.g. `10115 Berlin, DE` or
+/// `75001, Paris, FR`; `Los Angeles, CA` is a region. `90018, Los Angeles, CA` is therefore read as Canada, US
+/// addresses need their country.
+pub fn parse(address: &str) -> BillingAddress {
+    let mut parts: Vec<&str> = address.split(',').map(str::trim).filter(|part| !part.is_empty()).collect();
+    let mut parsed = BillingAddress::default();
+
+    let is_postal = |part: &str| POSTAL.is_match(part);
+    let is_city = |part: &str| !is_postal(part) && part.chars().any(char::is_alphabetic) && !part.chars().any(|c| c.is_ascii_digit());
+    // a postal code part followed by the city, or a part with postal code and city
+    let ends_with_postal_and_city = |parts: &[&str]| match parts {
+        [.., postal, city] if is_postal(postal) && is_city(city) => true,
+        [.., last] => POSTAL_CITY.is_match(last) || CITY_POSTAL.is_match(last),
+        [] => false,
+    };
+    if let Some(&last) = parts.last() {
+        let code = countries::code_of_name(last)
+            .or_else(|| Some(&parts[..parts.len() - 1]).filter(|head| ends_with_postal_and_city(head))
+                .and_then(|_| countries::known_code(last)));
+        if let Some(code) = code {
+            parsed.country = Some(last.to_string());
+            parsed.country_code = Some(code);
+            parts.pop();
+        } else if REGION.is_match(last) && parts.len() > 1 {
+            parsed.region = Some(last.to_string());
+            parts.pop();
+        }
+    }
+
+    let postal_index = parts.iter().enumerate().rposition(|(index, part)| {
+        is_postal(part) && parts.get(index + 1).is_some_and(|city| is_city(city))
+    });
+    let head = if let Some(index) = postal_index {
+        parsed.postal_code = Some(parts[index].to_string());
+        parsed.city = Some(parts[index + 1].to_string());
+        &parts[..index]
+    } else if let Some(captures) = parts.last().and_then(|last| POSTAL_CITY.captures(last)) {
+        // only the last part, a street like `3433 W Exposition Place` looks the same
+        parsed.postal_code = Some(captures[1].to_string());
+        parsed.city = Some(captures[2].trim().to_string());
+        &parts[..parts.len() - 1]
+    } else if let Some(captures) = parts.last().and_then(|last| CITY_POSTAL.captures(last)) {
+        parsed.city = Some(captures[1].trim().to_string());
+        parsed.region = captures.get(2).map(|region| region.as_str().to_string()).or(parsed.region);
+        parsed.postal_code = Some(captures[3].to_string());
+        &parts[..parts.len() - 1]
+    } else if let Some(postal) = parts.last().filter(|last| is_postal(last)) {
+        // the city is missing
+        parsed.postal_code = Some(postal.to_string());
+        &parts[..parts.len() - 1]
+    } else {
+        parsed.city = parts.last().map(|city| city.to_string());
+        &parts[..parts.len().saturating_sub(1)]
+    };
+
+    if let Some(captures) = parsed.city.as_deref().and_then(|city| CITY_REGION.captures(city)) {
+        let (city, region) = (captures[1].to_string(), captures[2].trim().to_string());
+        parsed.city = Some(city).filter(|city| !city.is_empty());
+        parsed.region = Some(region);
+    }
+
+    match head {
+        [] => {}
+        [street] => parsed.street = Some(street.to_string()),
+        [name, street @ ..] => {
+            parsed.name = Some(name.to_string());
+            parsed.street = Some(street.join(", "));
+        }
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(address: &str) -> [Option<String>; 6] {
+        let parsed = parse(address);
+        [parsed.name, parsed.street, parsed.postal_code, parsed.city, parsed.region,
+         parsed.country_code.map(str::to_string)]
+    }
+
+    fn expected(parts: [&str; 6]) -> [Option<String>; 6] {
+        parts.map(|part| Some(part.to_string()).filter(|part| !part.is_empty()))
+    }
+
+    #[test]
+    fn separate_postal_code_and_region_in_brackets() {
+        assert_eq!(parts("GOAT, 3433 W Exposition Place, 90018, Los Angeles (CA), USA "),
+                   expected(["GOAT", "3433 W Exposition Place", "90018", "Los Angeles", "CA", "US"]));
+    }
+
+    #[test]
+    fn postal_code_before_the_city() {
+        assert_eq!(parts("Shop GmbH, Hauptstr. 1, 10115 Berlin, Deutschland"),
+                   expected(["Shop GmbH", "Hauptstr. 1", "10115", "Berlin", "", "DE"]));
+        assert_eq!(parts("Shop GmbH, Hauptstr. 1, 10115 Berlin"),
+                   expected(["Shop GmbH", "Hauptstr. 1", "10115", "Berlin", "", ""]));
+        assert_eq!(parts("Firma BV, Keizersgracht 1, 1012 LG Amsterdam, Netherlands"),
+                   expected(["Firma BV", "Keizersgracht 1", "1012 LG", "Amsterdam", "", "NL"]));
+    }
+
+    #[test]
+    fn postal_code_after_the_city() {
+        assert_eq!(parts("Jane Doe, 10 Downing Street, London SW1A 2AA, United Kingdom"),
+                   expected(["Jane Doe", "10 Downing Street", "SW1A 2AA", "London", "", "GB"]));
+        assert_eq!(parts("Shop Inc, 1 King St W, Toronto ON M5V 2T6, Canada"),
+                   expected(["Shop Inc", "1 King St W", "M5V 2T6", "Toronto", "ON", "CA"]));
+    }
+
+    #[test]
+    fn trailing_states_are_no_countries() {
+        assert_eq!(parts("GOAT, 3433 W Exposition Place, Los Angeles, CA"),
+                   expected(["GOAT", "3433 W Exposition Place", "", "Los Angeles", "CA", ""]));
+        assert_eq!(parts("Acme Corp, 1000 N West St, Wilmington, DE"),
+                   expected(["Acme Corp", "1000 N West St", "", "Wilmington", "DE", ""]));
+        assert_eq!(parts("Acme Corp, 1000 N West St, Wilmington DE 19801"),
+                   expected(["Acme Corp", "1000 N West St", "19801", "Wilmington", "DE", ""]));
+    }
+
+    #[test]
+    fn two_letter_codes_after_postal_code_and_city() {
+        assert_eq!(parts("Shop GmbH, Hauptstr. 1, 10115 Berlin, DE"),
+                   expected(["Shop GmbH", "Hauptstr. 1", "10115", "Berlin", "", "DE"]));
+        assert_eq!(parts("Shop SARL, 1 Rue de Rivoli, 75001, Paris, FR"),
+                   expected(["Shop SARL", "1 Rue de Rivoli", "75001", "Paris", "", "FR"]));
+        assert_eq!(parts("Firma Sp. z o.o., ul. Nowy Swiat 1, 00-950, Warszawa, PL"),
+                   expected(["Firma Sp. z o.o.", "ul. Nowy Swiat 1", "00-950", "Warszawa", "", "PL"]));
+    }
+
+    #[test]
+    fn parts_that_only_look_like_postal_codes() {
+        assert_eq!(parts("Jane Doe, Main Street 1, APT 5B, Springfield, IL"),
+                   expected(["Jane Doe", "Main Street 1, APT 5B", "", "Springfield", "IL", ""]));
+        assert_eq!(parts("3M Deutschland GmbH, Carl-Schurz-Str. 1, 41453 Neuss, Deutschland"),
+                   expected(["3M Deutschland GmbH", "Carl-Schurz-Str. 1", "41453", "Neuss", "", "DE"]));
+        assert_eq!(parts("3M, Hauptstr. 1, 12, Berlin"),
+                   expected(["3M", "Hauptstr. 1, 12", "", "Berlin", "", ""]));
+        assert_eq!(parts("Shop GmbH, Hauptstr. 1, 10115"),
+                   expected(["Shop GmbH", "Hauptstr. 1", "10115", "", "", ""]));
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::path::Path;
+use log::debug;
+use serde::Serialize;
+use crate::error::Result;
+use crate::invoice::invoice::InvoiceCSV;
+use crate::report::render_table;
+use crate::settings::Config;
+use crate::state::{self, InvoiceRecord, InvoiceState, StateStore};
+
+/// What differs from the upload recorded in the state database.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Change {
+    /// a column of the csv row was corrected
+    Row,
+    /// the pdf was replaced
+    File,
+    /// the pdf is gone
+    FileMissing,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Change::Row => "csv row changed",
+            Change::File => "pdf changed",
+            Change::FileMissing => "pdf missing",
+        })
+    }
+}
+
+/// An uploaded invoice whose csv row or pdf no longer matches what was sent to lexoffice.
+#[derive(Debug)]
+pub struct ChangedInvoice<'a> {
+    pub invoice: &'a InvoiceCSV,
+    pub record: InvoiceRecord,
+    pub changes: Vec<Change>,
+}
+
+impl ChangedInvoice<'_> {
+    /// Only the voucher data changed and the voucher is known, so it can be updated through the api.
+    /// lexoffice has no endpoint to replace an attached file.
+    pub fn updatable(&self) -> bool {
+        self.changes == [Change::Row] && self.record.voucher_id.is_some()
+    }
+
+    /// Why the invoice has to be corrected by hand, recorded with `StateStore::record_flagged`.
+    pub fn correction(&self) -> String {
+        let mut reason = self.changes.iter().map(Change::to_string).collect::<Vec<_>>().join(", ");
+        if self.record.voucher_id.is_none() {
+            reason.push_str(", voucher id unknown");
+        }
+        reason
+    }
+}
+
+/// Compares the uploaded invoices with the hashes stored at upload time.
+/// Invoices imported from the done invoices csv have no hashes and are never reported.
+pub fn detect_changes<'a>(state: &StateStore, config: &Config, invoices: &[&'a InvoiceCSV])
+                          -> Result<Vec<ChangedInvoice<'a>>> {
+    let mut changed = vec![];
+    let mut without_baseline = 0;
+    for invoice in invoices {
+        let Some(record) = state.get(invoice.invoice_number())? else { continue };
+        if record.state != InvoiceState::FileUploaded {
+            continue;
+        }
+        if record.row_hash.is_none() && record.file_hash.is_none() {
+            without_baseline += 1;
+            continue;
+        }
+
+        let mut changes = vec![];
+        if record.row_hash.as_deref().is_some_and(|hash| hash != invoice.content_hash()) {
+            changes.push(Change::Row);
+        }
+        if let Some(uploaded_hash) = record.file_hash.as_deref() {
+            match invoice.mapped_file_path(config).filter(|path| Path::new(path).exists()) {
+                Some(path) if state::file_hash(&path)? != uploaded_hash => changes.push(Change::File),
+                Some(_) => {}
+                None => changes.push(Change::FileMissing),
+            }
+        }
+        if !changes.is_empty() {
+            changed.push(ChangedInvoice { invoice, record, changes });
+        }
+    }
+    if without_baseline > 0 {
+        debug!("{} uploaded invoices have no stored hashes and cannot be checked for changes", without_baseline);
+    }
+    Ok(changed)
+}
+
+pub fn render_changes(changed: &[ChangedInvoice<'_>]) -> String {
+    let rows: Vec<Vec<String>> = changed.iter().map(|change| vec![
+        change.invoice.invoice_number().to_string(),
+        change.record.voucher_id.clone().unwrap_or_default(),
+        change.changes.iter().map(Change::to_string).collect::<Vec<_>>().join(", "),
+        change.record.correction.clone().unwrap_or_default(),
+    ]).collect();
+    render_table(&["Invoice", "Voucher", "Changes", "Flagged"], &rows)
+}
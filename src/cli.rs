@@ -0,0 +1,326 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use chrono::NaiveDate;
+use cli_lexuploader::filter::InvoiceFilter;
+use cron::Schedule;
+use cli_lexuploader::logging::{LogSettings, Rotation};
+use cli_lexuploader::revenue::Dimension;
+use cli_lexuploader::settings::PaymentTerms;
+use cli_lexuploader::ErrorCategory;
+use log::LevelFilter;
+use regex::Regex;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Uploads invoices to lexoffice")]
+pub struct Cli {
+    /// Never prompt for input, fail instead (e.g. for scheduled runs)
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+
+    /// Use the named profile from lexUploadConfig.yaml instead of the top level settings
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Route every invoice to the profile that lists its prefix and process all profiles
+    #[arg(long, global = true, conflicts_with = "profile")]
+    pub all_profiles: bool,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Logging")]
+pub struct LogArgs {
+    /// One of off, error, warn, info, debug or trace
+    #[arg(long, global = true, env = "LEXUPLOAD_LOG_LEVEL", default_value = "info")]
+    pub log_level: LevelFilter,
+    /// Directory of output.log and its rotated files
+    #[arg(long, global = true, env = "LEXUPLOAD_LOG_DIR", default_value = "log")]
+    pub log_dir: PathBuf,
+    /// When to start a new log file
+    #[arg(long, global = true, value_enum, default_value = "size")]
+    pub log_rotation: LogRotation,
+    /// Size in bytes after which the log file is rotated with --log-rotation size
+    #[arg(long, global = true, default_value_t = 10_000_000)]
+    pub log_max_size: u64,
+    /// Number of rotated log files to keep
+    #[arg(long, global = true, default_value_t = 7)]
+    pub log_keep: u32,
+    /// Write the log file as json lines
+    #[arg(long, global = true)]
+    pub log_json: bool,
+    /// Hide billing addresses in the log, the api key is always hidden
+    #[arg(long, global = true)]
+    pub redact_addresses: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum LogRotation {
+    Size,
+    Daily,
+    Never,
+}
+
+impl LogArgs {
+    pub fn to_settings(&self) -> LogSettings {
+        LogSettings {
+            level: self.log_level,
+            dir: self.log_dir.clone(),
+            rotation: match self.log_rotation {
+                LogRotation::Size => Rotation::Size(self.log_max_size),
+                LogRotation::Daily => Rotation::Daily,
+                LogRotation::Never => Rotation::Never,
+            },
+            keep: self.log_keep,
+            json: self.log_json,
+            redact_addresses: self.redact_addresses,
+            quiet_console: false,
+        }
+    }
+}
+
+// parsed once per process, boxing the schedule would only make the clap attributes noisier
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Upload all invoices from invoices.csv that are not uploaded yet (default)
+    Upload(UploadArgs),
+    /// Upload only the invoices recorded in the failures file of the last runs
+    Retry {
+        /// Only retry failures of these categories, e.g. rate-limit,network
+        #[arg(long = "category", value_delimiter = ',')]
+        categories: Vec<ErrorCategory>,
+        #[command(flatten)]
+        upload: UploadArgs,
+    },
+    /// Keep running and upload new invoices whenever invoices.csv or a pdf changes
+    Watch {
+        /// Seconds to wait for further changes before uploading
+        #[arg(long, default_value_t = 5)]
+        debounce: u64,
+        #[command(flatten)]
+        upload: UploadArgs,
+    },
+    /// Upload on a schedule and report the status over http, never prompts for input
+    Daemon {
+        /// Cron expression with seconds, e.g. "0 0 8-18 * * Mon-Fri" for every full hour on workdays
+        #[arg(long, default_value = "0 0 * * * *")]
+        schedule: Schedule,
+        /// Address of the status endpoint
+        #[arg(long, default_value = "127.0.0.1:8787")]
+        listen: SocketAddr,
+        #[command(flatten)]
+        upload: UploadArgs,
+    },
+    /// Store the api key in a passphrase protected file instead of lexUploadConfig.yaml
+    EncryptKey {
+        /// Path of the encrypted secrets file
+        #[arg(long, default_value = "lexUploadKey.enc")]
+        output: String,
+    },
+    /// Manage the prefix to folder mappings
+    Prefix {
+        #[command(subcommand)]
+        action: PrefixCommand,
+    },
+    /// Manage the address to lexoffice contact mappings
+    Customer {
+        #[command(subcommand)]
+        action: CustomerCommand,
+    },
+    /// List prefixes and addresses in the invoice csv that are not mapped yet
+    Unmapped {
+        #[arg(long, default_value = "invoices.csv")]
+        csv: String,
+    },
+    /// List uploaded invoices whose csv row or pdf changed since the upload
+    Changed {
+        /// Update the vouchers of invoices whose csv row changed, if lexoffice allows it
+        #[arg(long)]
+        update: bool,
+        /// Flag the changed invoices that cannot be updated for manual correction in the state database
+        #[arg(long)]
+        flag: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Compare the uploaded vouchers in lexoffice with the csv rows and check that their pdf is attached
+    Verify {
+        /// Also write the results as json
+        #[arg(long)]
+        report_json: Option<String>,
+    },
+    /// Fetch the payment status of the uploaded vouchers and write an open items report
+    Payments {
+        /// Path of the open items csv
+        #[arg(long, default_value = "open_items.csv")]
+        report_csv: String,
+        /// Also write the open items as json
+        #[arg(long)]
+        report_json: Option<String>,
+    },
+    /// Sum net, tax and gross amounts of invoices.csv, e.g. per month and customer before closing a month
+    Revenue {
+        /// Group by these columns: month, prefix, customer, currency, tax-rate, status
+        #[arg(long, value_delimiter = ',', default_value = "month,currency")]
+        group_by: Vec<Dimension>,
+        /// Also write the summary as csv
+        #[arg(long)]
+        report_csv: Option<String>,
+        /// Also write the summary as json
+        #[arg(long)]
+        report_json: Option<String>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// List invoices whose tax case cannot be determined or whose VAT rate does not fit it
+    CheckTax {
+        #[arg(long, default_value = "invoices.csv")]
+        csv: String,
+    },
+    /// Inspect the upload state database
+    State {
+        #[command(subcommand)]
+        action: StateCommand,
+    },
+    /// Inspect lexUploadConfig.yaml
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+#[derive(Args, Debug, Default)]
+pub struct UploadArgs {
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    /// Upload at most this many invoices
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Do not show a progress bar or progress lines
+    #[arg(long)]
+    pub no_progress: bool,
+    /// Also write the end-of-run report as csv
+    #[arg(long)]
+    pub report_csv: Option<String>,
+    /// Also write the end-of-run report as json
+    #[arg(long)]
+    pub report_json: Option<String>,
+    /// Also write the end-of-run report as a self-contained html page
+    #[arg(long)]
+    pub report_html: Option<String>,
+}
+
+impl UploadArgs {
+    pub fn to_filter(&self) -> InvoiceFilter {
+        InvoiceFilter { limit: self.limit, ..self.filter.to_filter() }
+    }
+}
+
+/// Selects which invoices of the csv are processed, dates are given as YYYY-MM-DD.
+#[derive(Args, Debug, Default)]
+pub struct FilterArgs {
+    /// Only invoices dated on or after this day
+    #[arg(long)]
+    pub invoice_date_from: Option<NaiveDate>,
+    /// Only invoices dated on or before this day
+    #[arg(long)]
+    pub invoice_date_to: Option<NaiveDate>,
+    /// Only invoices delivered on or after this day
+    #[arg(long)]
+    pub delivery_date_from: Option<NaiveDate>,
+    /// Only invoices delivered on or before this day
+    #[arg(long)]
+    pub delivery_date_to: Option<NaiveDate>,
+    /// Only invoices with these prefixes
+    #[arg(long = "prefix", value_delimiter = ',')]
+    pub prefixes: Vec<String>,
+    /// Only invoices with these transaction types, e.g. b2b
+    #[arg(long = "transaction-type", value_delimiter = ',')]
+    pub transaction_types: Vec<String>,
+    /// Only invoices of this billing address or lexoffice contact id, can be repeated
+    #[arg(long = "customer")]
+    pub customers: Vec<String>,
+    /// Only these invoice numbers
+    #[arg(long = "invoice-number", value_delimiter = ',')]
+    pub invoice_numbers: Vec<String>,
+    /// Only invoice numbers matching this regular expression
+    #[arg(long)]
+    pub invoice_number_regex: Option<Regex>,
+}
+
+impl FilterArgs {
+    pub fn to_filter(&self) -> InvoiceFilter {
+        InvoiceFilter {
+            invoice_date_from: self.invoice_date_from,
+            invoice_date_to: self.invoice_date_to,
+            delivery_date_from: self.delivery_date_from,
+            delivery_date_to: self.delivery_date_to,
+            prefixes: self.prefixes.clone(),
+            transaction_types: self.transaction_types.clone(),
+            customers: self.customers.clone(),
+            invoice_numbers: self.invoice_numbers.clone(),
+            invoice_number_pattern: self.invoice_number_regex.clone(),
+            limit: None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StateCommand {
+    /// Mark the invoices of a done invoices csv as uploaded (defaults to the ledger of the profile)
+    Import { file: Option<String> },
+    /// Write the uploaded invoices as done invoices csv (defaults to the ledger of the profile)
+    Export { file: Option<String> },
+    /// Print the state and history of one invoice
+    Show { invoice_number: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Report unknown keys, duplicate prefixes and addresses and malformed uuids
+    Validate,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PrefixCommand {
+    /// List all prefixes
+    List,
+    /// Add a prefix or change its folder
+    Add { prefix: String, path: String },
+    /// Remove a prefix
+    Remove { prefix: String },
+    /// Rename a prefix and keep its folder
+    Rename { old: String, new: String },
+    /// Import prefixes from a csv with the columns prefix,path
+    Import { file: String },
+    /// Export prefixes to a csv with the columns prefix,path
+    Export { file: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CustomerCommand {
+    /// List all customers
+    List,
+    /// Add an address or change its lexoffice contact id
+    Add {
+        address: String,
+        customer_id: String,
+        /// e.g. "net 30" or "end of month", overrides the payment terms of the profile
+        #[arg(long)]
+        payment_terms: Option<PaymentTerms>,
+    },
+    /// Remove an address
+    Remove { address: String },
+    /// Change the address of a mapping and keep its contact id
+    Rename { old: String, new: String },
+    /// Import customers from a csv with the columns customer_id,customer_adress and optionally payment_terms
+    Import { file: String },
+    /// Export customers to a csv with the columns customer_id,customer_adress,payment_terms
+    Export { file: String },
+}
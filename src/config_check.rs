@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use regex::Regex;
+use serde_yaml::{Mapping, Value};
+use uuid::Uuid;
+use crate::error::Result;
+use crate::migration::{config_version, CURRENT_VERSION};
+use crate::invoice::invoice::REMARK_PLACEHOLDERS;
+use crate::settings::{PaymentTerms, DEFAULT_PROFILE_NAME};
+use crate::countries;
+use crate::tax::TaxCase;
+
+const ROOT_KEYS: &[&str] = &["version", "api_key", "api_key_file", "api_key_encrypted_file", "prefixes",
+    "customers", "categories", "ledger", "payment_terms", "remark", "home_country", "tax_cases", "profiles"];
+const PROFILE_KEYS: &[&str] = &["name", "api_key", "api_key_file", "api_key_encrypted_file", "prefixes",
+    "customers", "categories", "ledger", "payment_terms", "remark", "home_country", "tax_cases"];
+const PREFIX_KEYS: &[&str] = &["prefix", "path"];
+const CUSTOMER_KEYS: &[&str] = &["customer_id", "customer_adress", "payment_terms"];
+const CATEGORY_KEYS: &[&str] = &["transaction_type", "case", "category_id"];
+const TAX_CASE_KEYS: &[&str] = &["case", "tax_type", "category_id", "rates"];
+const TAX_CASES: &[TaxCase] = &[TaxCase::Domestic, TaxCase::IntraCommunity, TaxCase::Oss, TaxCase::Export];
+
+/// Checks the raw config file for mistakes confy silently accepts.
+/// Returns one human readable line per problem, an empty list means the file is fine.
+pub fn validate_config_file(path: &str) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)?;
+    let config = match serde_yaml::from_str::<Value>(&content)? {
+        Value::Mapping(config) => config,
+        _ => return Ok(vec![format!("{} does not contain a yaml mapping", path)]),
+    };
+
+    let mut issues = vec![];
+    match config_version(&config) {
+        Ok(version) if version != CURRENT_VERSION =>
+            issues.push(format!("version is {}, expected {} (the file is migrated on the next run)", version, CURRENT_VERSION)),
+        Ok(_) => {}
+        Err(e) => issues.push(e.to_string()),
+    }
+
+    check_scope(&config, "", ROOT_KEYS, &mut issues);
+
+    // the same prefix in two profiles makes routing by prefix ambiguous
+    let mut prefix_owners: HashMap<String, String> = HashMap::new();
+    collect_prefixes(&config, DEFAULT_PROFILE_NAME, &mut prefix_owners, &mut issues);
+
+    for (index, profile) in sequence(&config, "profiles").iter().enumerate() {
+        let profile = match profile {
+            Value::Mapping(profile) => profile,
+            _ => {
+                issues.push(format!("profiles[{}] is not a mapping", index));
+                continue;
+            }
+        };
+        let name = profile.get(&Value::from("name")).and_then(Value::as_str);
+        let location = match name {
+            Some(name) => format!("profiles[{}] ({})", index, name),
+            None => {
+                issues.push(format!("profiles[{}] has no name", index));
+                format!("profiles[{}]", index)
+            }
+        };
+        check_scope(profile, &location, PROFILE_KEYS, &mut issues);
+        collect_prefixes(profile, name.unwrap_or(&location), &mut prefix_owners, &mut issues);
+    }
+
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    for profile in sequence(&config, "profiles") {
+        if let Some(name) = profile.get("name").and_then(Value::as_str) {
+            *names.entry(name).or_default() += 1;
+        }
+    }
+    for (name, count) in names {
+        if name == DEFAULT_PROFILE_NAME {
+            issues.push(format!("profile name {} is reserved for the top level settings", name));
+        }
+        if count > 1 {
+            issues.push(format!("profile name {} is used {} times", name, count));
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Checks the keys, prefixes, customers and categories of the top level or of one profile.
+fn check_scope(scope: &Mapping, location: &str, known_keys: &[&str], issues: &mut Vec<String>) {
+    let prefix = if location.is_empty() { String::new() } else { format!("{}.", location) };
+    check_keys(scope, location, known_keys, issues);
+    check_payment_terms(scope.get(&Value::from("payment_terms")), &format!("{}payment_terms", prefix), issues);
+    check_remark(scope.get(&Value::from("remark")), &format!("{}remark", prefix), issues);
+
+    let mut seen_prefixes: HashMap<String, usize> = HashMap::new();
+    for (index, entry) in sequence(scope, "prefixes").iter().enumerate() {
+        let location = format!("{}prefixes[{}]", prefix, index);
+        check_keys_of(entry, &location, PREFIX_KEYS, issues);
+        match entry.get("prefix").and_then(Value::as_str) {
+            Some(value) => {
+                if let Some(first) = seen_prefixes.insert(value.to_string(), index) {
+                    issues.push(format!("{}: duplicate prefix {} (first defined in {}prefixes[{}])", location, value, prefix, first));
+                }
+            }
+            None => issues.push(format!("{}: prefix is missing", location)),
+        }
+        if entry.get("path").and_then(Value::as_str).is_none() {
+            issues.push(format!("{}: path is missing", location));
+        }
+    }
+
+    let mut seen_addresses: HashMap<String, usize> = HashMap::new();
+    for (index, entry) in sequence(scope, "customers").iter().enumerate() {
+        let location = format!("{}customers[{}]", prefix, index);
+        check_keys_of(entry, &location, CUSTOMER_KEYS, issues);
+        match entry.get("customer_adress").and_then(Value::as_str) {
+            Some(address) => {
+                if let Some(first) = seen_addresses.insert(address.to_string(), index) {
+                    issues.push(format!("{}: duplicate address {} (first defined in {}customers[{}])", location, address, prefix, first));
+                }
+            }
+            None => issues.push(format!("{}: customer_adress is missing", location)),
+        }
+        check_uuid(entry, "customer_id", &location, issues);
+        check_payment_terms(entry.get("payment_terms"), &format!("{}.payment_terms", location), issues);
+    }
+
+    for (index, entry) in sequence(scope, "categories").iter().enumerate() {
+        let location = format!("{}categories[{}]", prefix, index);
+        check_keys_of(entry, &location, CATEGORY_KEYS, issues);
+        check_uuid(entry, "category_id", &location, issues);
+        match entry.get("case").map(|case| serde_yaml::from_value::<TaxCase>(case.clone())) {
+            Some(Ok(case)) => {
+                let category_id = entry.get("category_id").and_then(Value::as_str);
+                if let Some(other) = TAX_CASES.iter().find(|other| **other != case && Some(other.default_category_id()) == category_id) {
+                    issues.push(format!("{}: category_id is the category of the tax case {}, not of {}", location, other, case));
+                }
+            }
+            Some(Err(_)) => issues.push(format!("{}: case must be one of domestic, intra-community, oss, export", location)),
+            None => issues.push(format!("{}: case is missing, the category is not used for any invoice", location)),
+        }
+    }
+
+    if let Some(country) = scope.get(&Value::from("home_country")) {
+        if country.as_str().and_then(countries::code).is_none() {
+            issues.push(format!("{}home_country is not a known country code", prefix));
+        }
+    }
+    let mut seen_cases: HashMap<String, usize> = HashMap::new();
+    for (index, entry) in sequence(scope, "tax_cases").iter().enumerate() {
+        let location = format!("{}tax_cases[{}]", prefix, index);
+        check_keys_of(entry, &location, TAX_CASE_KEYS, issues);
+        match entry.get("case").map(|case| serde_yaml::from_value::<TaxCase>(case.clone())) {
+            Some(Ok(case)) => {
+                if let Some(first) = seen_cases.insert(case.to_string(), index) {
+                    issues.push(format!("{}: duplicate case {} (first defined in {}tax_cases[{}])", location, case, prefix, first));
+                }
+            }
+            Some(Err(_)) => issues.push(format!("{}: case must be one of domestic, intra-community, oss, export", location)),
+            None => issues.push(format!("{}: case is missing", location)),
+        }
+        if let Some(tax_type) = entry.get("tax_type") {
+            if !matches!(tax_type.as_str(), Some("net") | Some("gross")) {
+                issues.push(format!("{}: tax_type must be net or gross", location));
+            }
+        }
+        if entry.get("category_id").is_some() {
+            check_uuid(entry, "category_id", &location, issues);
+        }
+    }
+}
+
+fn collect_prefixes(scope: &Mapping, owner: &str, owners: &mut HashMap<String, String>, issues: &mut Vec<String>) {
+    let own: HashSet<String> = sequence(scope, "prefixes").iter()
+        .filter_map(|entry| entry.get("prefix").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect();
+    for value in own {
+        match owners.get(&value) {
+            Some(other) if other != owner =>
+                issues.push(format!("prefix {} is configured in both {} and {}", value, other, owner)),
+            Some(_) => {}
+            None => {
+                owners.insert(value, owner.to_string());
+            }
+        }
+    }
+}
+
+fn check_payment_terms(value: Option<&Value>, location: &str, issues: &mut Vec<String>) {
+    match value {
+        None | Some(Value::Null) => {}
+        Some(Value::String(terms)) => {
+            if let Err(e) = terms.parse::<PaymentTerms>() {
+                issues.push(format!("{}: {}", location, e));
+            }
+        }
+        Some(_) => issues.push(format!("{} is not a string", location)),
+    }
+}
+
+fn check_remark(value: Option<&Value>, location: &str, issues: &mut Vec<String>) {
+    let template = match value {
+        None | Some(Value::Null) => return,
+        Some(Value::String(template)) => template,
+        Some(_) => return issues.push(format!("{} is not a string", location)),
+    };
+    let placeholder = Regex::new(r"\{([^{}]*)\}").expect("static regex is valid");
+    for captures in placeholder.captures_iter(template) {
+        if !REMARK_PLACEHOLDERS.contains(&&captures[1]) {
+            issues.push(format!("{}: unknown placeholder {{{}}}, known are {}", location, &captures[1], REMARK_PLACEHOLDERS.join(", ")));
+        }
+    }
+}
+
+fn check_uuid(entry: &Value, key: &str, location: &str, issues: &mut Vec<String>) {
+    match entry.get(key).and_then(Value::as_str) {
+        Some(id) if Uuid::parse_str(id).is_err() => issues.push(format!("{}: {} {} is not a valid uuid", location, key, id)),
+        Some(_) => {}
+        None => issues.push(format!("{}: {} is missing", location, key)),
+    }
+}
+
+fn check_keys_of(entry: &Value, location: &str, known_keys: &[&str], issues: &mut Vec<String>) {
+    match entry {
+        Value::Mapping(entry) => check_keys(entry, location, known_keys, issues),
+        _ => issues.push(format!("{} is not a mapping", location)),
+    }
+}
+
+fn check_keys(scope: &Mapping, location: &str, known_keys: &[&str], issues: &mut Vec<String>) {
+    for (key, _) in scope.iter() {
+        let known = key.as_str().is_some_and(|key| known_keys.contains(&key));
+        if !known {
+            let key = serde_yaml::to_string(key).unwrap_or_default().trim_start_matches("---").trim().to_string();
+            if location.is_empty() {
+                issues.push(format!("unknown key {}", key));
+            } else {
+                issues.push(format!("{}: unknown key {}", location, key));
+            }
+        }
+    }
+}
+
+fn sequence<'a>(scope: &'a Mapping, key: &str) -> &'a [Value] {
+    match scope.get(&Value::from(key)) {
+        Some(Value::Sequence(entries)) => entries,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remark_issues(template: &str) -> Vec<String> {
+        let mut issues = vec![];
+        check_remark(Some(&Value::from(template)), "remark", &mut issues);
+        issues
+    }
+
+    #[test]
+    fn unknown_remark_placeholders_are_reported_once_per_use() {
+        assert!(remark_issues("{internal_reference} {internal_reference} via {prefix}").is_empty());
+        let issues = remark_issues("{order_id} {order_id} {prefix}");
+        assert_eq!(issues.len(), 2);
+        assert!(issues[0].starts_with("remark: unknown placeholder {order_id}"), "{}", issues[0]);
+        assert_eq!(remark_issues("{}").len(), 1);
+    }
+
+    fn category_issues(categories: &str) -> Vec<String> {
+        let scope: Mapping = serde_yaml::from_str(&format!("categories:\n{}", categories)).expect("valid yaml");
+        let mut issues = vec![];
+        check_scope(&scope, "", ROOT_KEYS, &mut issues);
+        issues
+    }
+
+    #[test]
+    fn categories_need_a_matching_tax_case() {
+        let domestic = "8f8664a1-fd86-11e1-a21f-0800200c9a66";
+        let intra_community = "9075a4e3-66de-4795-a016-3889feca0d20";
+        let custom = "0e5b4f1a-2c3d-4e5f-8a9b-0c1d2e3f4a5b";
+        let entry = |case: &str, id: &str| format!("  - transaction_type: b2b\n{}    category_id: {}\n", case, id);
+
+        assert!(category_issues(&entry("    case: domestic\n", custom)).is_empty());
+        assert!(category_issues(&entry("    case: intra-community\n", intra_community)).is_empty());
+        assert_eq!(category_issues(&entry("", intra_community)),
+                   vec!["categories[0]: case is missing, the category is not used for any invoice"]);
+        assert_eq!(category_issues(&entry("    case: domestic\n", intra_community)),
+                   vec!["categories[0]: category_id is the category of the tax case intra-community, not of domestic"]);
+        assert!(category_issues(&entry("    case: export\n", domestic))[0].contains("tax case domestic, not of export"));
+        assert_eq!(category_issues(&entry("    case: reverse-charge\n", custom)).len(), 1);
+    }
+}
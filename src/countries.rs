@@ -0,0 +1,81 @@
+/// Member states of the European Union as ISO 3166-1 alpha-2 codes. Greece is `GR` here, VAT ids use `EL`.
+pub const EU_COUNTRIES: &[&str] = &["AT", "BE", "BG", "CY", "CZ", "DE", "DK", "EE", "ES", "FI", "FR", "GR", "HR", "HU",
+    "IE", "IT", "LT", "LU", "LV", "MT", "NL", "PL", "PT", "RO", "SE", "SI", "SK"];
+
+/// Country names as they appear in the exports, in English and German, lower case.
+const NAMES: &[(&str, &str)] = &[
+    ("austria", "AT"), ("österreich", "AT"),
+    ("belgium", "BE"), ("belgien", "BE"),
+    ("bulgaria", "BG"), ("bulgarien", "BG"),
+    ("cyprus", "CY"), ("zypern", "CY"),
+    ("czech republic", "CZ"), ("czechia", "CZ"), ("tschechien", "CZ"), ("tschechische republik", "CZ"),
+    ("germany", "DE"), ("deutschland", "DE"),
+    ("denmark", "DK"), ("dänemark", "DK"),
+    ("estonia", "EE"), ("estland", "EE"),
+    ("spain", "ES"), ("spanien", "ES"),
+    ("finland", "FI"), ("finnland", "FI"),
+    ("france", "FR"), ("frankreich", "FR"),
+    ("greece", "GR"), ("griechenland", "GR"),
+    ("croatia", "HR"), ("kroatien", "HR"),
+    ("hungary", "HU"), ("ungarn", "HU"),
+    ("ireland", "IE"), ("irland", "IE"),
+    ("italy", "IT"), ("italien", "IT"),
+    ("lithuania", "LT"), ("litauen", "LT"),
+    ("luxembourg", "LU"), ("luxemburg", "LU"),
+    ("latvia", "LV"), ("lettland", "LV"),
+    ("malta", "MT"),
+    ("netherlands", "NL"), ("the netherlands", "NL"), ("niederlande", "NL"), ("holland", "NL"),
+    ("poland", "PL"), ("polen", "PL"),
+    ("portugal", "PT"),
+    ("romania", "RO"), ("rumänien", "RO"),
+    ("sweden", "SE"), ("schweden", "SE"),
+    ("slovenia", "SI"), ("slowenien", "SI"),
+    ("slovakia", "SK"), ("slowakei", "SK"),
+    ("usa", "US"), ("united states", "US"), ("united states of america", "US"), ("vereinigte staaten", "US"),
+    ("united kingdom", "GB"), ("uk", "GB"), ("great britain", "GB"), ("england", "GB"), ("scotland", "GB"),
+    ("wales", "GB"), ("großbritannien", "GB"), ("vereinigtes königreich", "GB"),
+    ("switzerland", "CH"), ("schweiz", "CH"),
+    ("norway", "NO"), ("norwegen", "NO"),
+    ("iceland", "IS"), ("island", "IS"),
+    ("liechtenstein", "LI"),
+    ("canada", "CA"), ("kanada", "CA"),
+    ("mexico", "MX"), ("mexiko", "MX"),
+    ("australia", "AU"), ("australien", "AU"),
+    ("new zealand", "NZ"), ("neuseeland", "NZ"),
+    ("japan", "JP"),
+    ("china", "CN"),
+    ("hong kong", "HK"), ("hongkong", "HK"),
+    ("singapore", "SG"), ("singapur", "SG"),
+    ("south korea", "KR"), ("korea", "KR"), ("südkorea", "KR"),
+    ("taiwan", "TW"),
+    ("united arab emirates", "AE"), ("uae", "AE"), ("vereinigte arabische emirate", "AE"),
+    ("israel", "IL"),
+    ("turkey", "TR"), ("türkei", "TR"),
+    ("ukraine", "UA"),
+    ("serbia", "RS"), ("serbien", "RS"),
+    ("brazil", "BR"), ("brasilien", "BR"),
+];
+
+/// ISO code of a country name or code, e.g. `USA` -> `US`, `Deutschland` -> `DE`, `fr` -> `FR`.
+pub fn code(name: &str) -> Option<&'static str> {
+    code_of_name(name).or_else(|| known_code(name))
+}
+
+/// ISO code of a country name, two letter codes are not accepted, see `known_code`.
+pub fn code_of_name(name: &str) -> Option<&'static str> {
+    let name = name.trim().trim_end_matches('.').to_lowercase();
+    NAMES.iter().find(|(known, _)| *known == name).map(|(_, code)| *code)
+}
+
+/// A known two letter code, `EL` is Greece. In addresses these collide with US states and Canadian provinces.
+pub fn known_code(code: &str) -> Option<&'static str> {
+    let upper = code.trim().trim_end_matches('.').to_uppercase();
+    let upper = if upper == "EL" { "GR".to_string() } else { upper };
+    EU_COUNTRIES.iter().chain(NAMES.iter().map(|(_, code)| code))
+        .find(|code| **code == upper)
+        .copied()
+}
+
+pub fn is_eu(code: &str) -> bool {
+    EU_COUNTRIES.contains(&code)
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use log::{error, info, warn};
+use serde::Serialize;
+use tiny_http::{Header, Method, Response, Server};
+use cli_lexuploader::report::RunReport;
+use cli_lexuploader::{mapping, read_invoice_csv, settings, Uploader};
+use crate::cli::{Cli, UploadArgs};
+use crate::progress::ProgressMode;
+use crate::INVOICE_CSV;
+
+/// State reported by `GET /status`.
+#[derive(Serialize, Debug)]
+struct DaemonStatus {
+    started_at: DateTime<Local>,
+    schedule: String,
+    next_run: Option<DateTime<Local>>,
+    running: bool,
+    last_run: Option<RunReport>,
+    /// set if the last run could not even read the settings or the csv
+    last_run_error: Option<String>,
+    pending_invoices: Vec<String>,
+    unmapped_addresses: Vec<String>,
+}
+
+impl DaemonStatus {
+    fn healthy(&self) -> bool {
+        self.last_run_error.is_none() && self.last_run.as_ref().is_none_or(|run| run.failed_profiles.is_empty())
+    }
+}
+
+/// Uploads on every tick of `schedule` and serves the status on `listen` until Ctrl-C.
+pub async fn daemon(cli: &Cli, args: &UploadArgs, schedule: &Schedule, listen: SocketAddr, progress: ProgressMode) {
+    let status = Arc::new(Mutex::new(DaemonStatus {
+        started_at: Local::now(),
+        schedule: schedule.to_string(),
+        next_run: None,
+        running: false,
+        last_run: None,
+        last_run_error: None,
+        pending_invoices: vec![],
+        unmapped_addresses: vec![],
+    }));
+
+    let server = match Server::http(listen) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("Could not listen on {}: {}", listen, e);
+            std::process::exit(1);
+        }
+    };
+    info!("Serving the status on http://{}/status", listen);
+    let server_status = Arc::clone(&status);
+    std::thread::spawn(move || serve(server, server_status));
+
+    update_backlog(&status, cli, args);
+    loop {
+        let Some(next) = schedule.upcoming(Local).next() else {
+            error!("The schedule {} has no upcoming runs", schedule);
+            break;
+        };
+        lock(&status).next_run = Some(next);
+        info!("Next upload at {}", next.format("%Y-%m-%d %H:%M:%S"));
+
+        let wait = (next - Local::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {},
+            _ = tokio::signal::ctrl_c() => {
+                info!("Stopped the daemon");
+                break;
+            }
+        }
+
+        lock(&status).running = true;
+        let report = crate::run_uploads(cli, args, None, progress, false).await;
+        if let Some(report) = &report {
+            print!("{}", report.render_text());
+            crate::write_report(report, args);
+        }
+        {
+            let mut status = lock(&status);
+            status.running = false;
+            status.last_run_error = match report {
+                Some(_) => None,
+                None => Some(format!("Could not read the settings or {}, see the log", INVOICE_CSV)),
+            };
+            status.last_run = report;
+        }
+        update_backlog(&status, cli, args);
+    }
+}
+
+fn lock(status: &Mutex<DaemonStatus>) -> std::sync::MutexGuard<'_, DaemonStatus> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Refreshes the invoices that are not uploaded yet and the addresses without a contact.
+fn update_backlog(status: &Mutex<DaemonStatus>, cli: &Cli, args: &UploadArgs) {
+    let settings = settings::load_settings().and_then(|settings| crate::select_configs(&settings, cli));
+    let invoices = read_invoice_csv(INVOICE_CSV.to_string());
+    let (configs, invoices) = match (settings, invoices) {
+        (Ok(configs), Ok(invoices)) => (configs, invoices),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Could not determine the pending invoices: {}", e);
+            return;
+        }
+    };
+
+    let filter = args.to_filter();
+    let mut pending = vec![];
+    let mut unmapped = BTreeSet::new();
+    for (config, batch) in settings::route(configs, &invoices, cli.all_profiles) {
+        let batch = filter.apply(batch, &config);
+        unmapped.extend(mapping::unmapped(&config, batch.iter().copied()).1);
+        match Uploader::new(config).pending(&batch) {
+            Ok(invoices) => pending.extend(invoices.iter().map(|invoice| invoice.invoice_number().to_string())),
+            Err(e) => warn!("Could not determine the pending invoices: {}", e),
+        }
+    }
+
+    let mut status = lock(status);
+    status.pending_invoices = pending;
+    status.unmapped_addresses = unmapped.into_iter().collect();
+}
+
+fn serve(server: Server, status: Arc<Mutex<DaemonStatus>>) {
+    let json = Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    for request in server.incoming_requests() {
+        let (code, body) = match (request.method(), request.url()) {
+            (Method::Get, "/health") => {
+                let healthy = lock(&status).healthy();
+                (if healthy { 200 } else { 503 }, format!("{{\"status\":\"{}\"}}", if healthy { "ok" } else { "failing" }))
+            }
+            (Method::Get, "/status") => match serde_json::to_string_pretty(&*lock(&status)) {
+                Ok(body) => (200, body),
+                Err(e) => (500, format!("{{\"error\":\"{}\"}}", e)),
+            },
+            _ => (404, "{\"error\":\"not found\"}".to_string()),
+        };
+        let response = Response::from_string(body).with_status_code(code).with_header(json.clone());
+        if let Err(e) = request.respond(response) {
+            warn!("Could not answer status request: {}", e);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Which kind of mapping was missing for an invoice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingKind {
+    Prefix,
+    Customer,
+}
+
+/// One field level complaint of lexoffice about a rejected request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationDetail {
+    /// the offending field, e.g. `voucherItems[0].categoryId`
+    pub field: Option<String>,
+    /// machine readable kind of violation, e.g. `NOTNULL` or `missing_entity`
+    pub violation: Option<String>,
+    pub message: Option<String>,
+}
+
+impl fmt::Display for ValidationDetail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = [&self.field, &self.violation, &self.message].iter()
+            .filter_map(|part| part.as_deref())
+            .collect::<Vec<_>>()
+            .join(": ");
+        f.write_str(&text)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// lexUploadConfig.yaml could not be read, written or is inconsistent
+    Config(String),
+    /// a csv file could not be read or parsed
+    Csv { path: String, message: String },
+    /// a json file could not be parsed or a report could not be serialized
+    Json { path: String, message: String },
+    /// the pdf of an invoice does not exist
+    MissingFile(String),
+    /// the pdf of an invoice is empty or larger than lexoffice accepts
+    FileTooLarge { path: String, size: u64 },
+    /// no folder or contact is configured and none could be asked for
+    MappingMissing { kind: MappingKind, key: String },
+    /// lexoffice rejected the api key
+    ApiAuth(String),
+    /// lexoffice rejected the voucher, `details` holds the field level messages
+    ApiValidation { status: u16, message: String, details: Vec<ValidationDetail> },
+    /// too many requests, `retry_after` is the delay in seconds lexoffice asked for
+    RateLimit { retry_after: Option<u64> },
+    /// any other unexpected status code from lexoffice
+    Api { status: u16, message: String },
+    Network(reqwest::Error),
+    Secrets(String),
+    /// the tax case of an invoice cannot be determined or its VAT rate does not fit the case
+    Tax { invoice: String, message: String },
+    /// the state database could not be opened, read or written
+    State(rusqlite::Error),
+    Io(io::Error),
+}
+
+/// Coarse grouping of `Error` used for reports and for selecting invoices to retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCategory {
+    Config,
+    Csv,
+    MissingFile,
+    FileTooLarge,
+    MappingMissing,
+    Tax,
+    ApiAuth,
+    ApiValidation,
+    RateLimit,
+    Api,
+    Network,
+    Other,
+}
+
+impl Error {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Config(_) => ErrorCategory::Config,
+            Error::Csv { .. } => ErrorCategory::Csv,
+            Error::MissingFile(_) => ErrorCategory::MissingFile,
+            Error::FileTooLarge { .. } => ErrorCategory::FileTooLarge,
+            Error::MappingMissing { .. } => ErrorCategory::MappingMissing,
+            Error::Tax { .. } => ErrorCategory::Tax,
+            Error::ApiAuth(_) => ErrorCategory::ApiAuth,
+            Error::ApiValidation { .. } => ErrorCategory::ApiValidation,
+            Error::RateLimit { .. } => ErrorCategory::RateLimit,
+            Error::Api { .. } => ErrorCategory::Api,
+            Error::Network(_) => ErrorCategory::Network,
+            Error::Json { .. } | Error::Secrets(_) | Error::State(_) | Error::Io(_) => ErrorCategory::Other,
+        }
+    }
+
+    /// The http status of lexoffice errors.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::ApiAuth(_) => Some(401),
+            Error::ApiValidation { status, .. } | Error::Api { status, .. } => Some(*status),
+            Error::RateLimit { .. } => Some(429),
+            _ => None,
+        }
+    }
+
+    /// The field level messages of a rejected voucher, empty for every other error.
+    pub fn details(&self) -> &[ValidationDetail] {
+        match self {
+            Error::ApiValidation { details, .. } => details,
+            _ => &[],
+        }
+    }
+
+    pub(crate) fn config<S: Into<String>>(message: S) -> Self {
+        Error::Config(message.into())
+    }
+
+    pub(crate) fn csv<E: fmt::Display>(path: &str, error: E) -> Self {
+        Error::Csv { path: path.to_string(), message: error.to_string() }
+    }
+
+    pub(crate) fn json(path: &str, error: serde_json::Error) -> Self {
+        Error::Json { path: path.to_string(), message: error.to_string() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message) => write!(f, "Config error: {}", message),
+            Error::Csv { path, message } => write!(f, "Could not read {}: {}", path, message),
+            Error::Json { path, message } => write!(f, "Invalid json for {}: {}", path, message),
+            Error::MissingFile(path) => write!(f, "File {} does not exist", path),
+            Error::FileTooLarge { path, size: 0 } => write!(f, "File {} is empty", path),
+            Error::FileTooLarge { path, size } => write!(f, "File {} is too large ({} bytes)", path, size),
+            Error::MappingMissing { kind: MappingKind::Prefix, key } => write!(f, "No folder configured for prefix {}", key),
+            Error::MappingMissing { kind: MappingKind::Customer, key } => write!(f, "No customer id configured for address {}", key),
+            Error::Tax { invoice, message } => write!(f, "Tax problem with invoice {}: {}", invoice, message),
+            Error::ApiAuth(message) => write!(f, "Api key rejected: {}", message),
+            Error::ApiValidation { status, message, details } => {
+                write!(f, "Voucher rejected with status {}: {}", status, message)?;
+                if !details.is_empty() {
+                    let details = details.iter().map(ValidationDetail::to_string).collect::<Vec<_>>();
+                    write!(f, " ({})", details.join("; "))?;
+                }
+                Ok(())
+            }
+            Error::RateLimit { retry_after: Some(seconds) } => write!(f, "Rate limit exceeded, retry after {}s", seconds),
+            Error::RateLimit { retry_after: None } => write!(f, "Rate limit exceeded"),
+            Error::Api { status, message } => write!(f, "lexoffice responded with status {}: {}", status, message),
+            Error::Network(e) => write!(f, "Network error: {}", e),
+            Error::Secrets(message) => write!(f, "Secrets error: {}", message),
+            Error::State(e) => write!(f, "State database error: {}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = serde_yaml::to_value(self).ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default();
+        f.write_str(&name)
+    }
+}
+
+impl FromStr for ErrorCategory {
+    type Err = String;
+
+    /// Parses the kebab-case names used in the failures file, e.g. `rate-limit`.
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        serde_yaml::from_value(serde_yaml::Value::from(name))
+            .map_err(|_| format!("unknown error category {}", name))
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(e) => Some(e),
+            Error::State(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Network(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::State(e)
+    }
+}
+
+impl From<confy::ConfyError> for Error {
+    fn from(e: confy::ConfyError) -> Self {
+        Error::Config(e.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Error::Config(e.to_string())
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use crate::error::{Error, ErrorCategory, Result, ValidationDetail};
+use crate::uploader::UploadSummary;
+
+/// An invoice whose last upload attempt failed, as stored in the failures file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailureRecord {
+    pub invoice_number: String,
+    pub category: ErrorCategory,
+    /// http status returned by lexoffice, if the request got that far
+    pub status: Option<u16>,
+    pub message: String,
+    #[serde(default)]
+    pub details: Vec<ValidationDetail>,
+    pub failed_at: DateTime<Local>,
+    /// number of failed attempts so far
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl FailureRecord {
+    pub fn new(invoice_number: &str, error: &Error) -> Self {
+        Self {
+            invoice_number: invoice_number.to_string(),
+            category: error.category(),
+            status: error.status(),
+            message: error.to_string(),
+            details: error.details().to_vec(),
+            failed_at: Local::now(),
+            attempts: 1,
+        }
+    }
+}
+
+/// Reads the failures file, a missing file means there are no failures.
+pub fn read_failures(path: &str) -> Result<Vec<FailureRecord>> {
+    if !Path::new(path).exists() {
+        return Ok(vec![]);
+    }
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| Error::json(path, e))
+}
+
+pub fn write_failures(path: &str, failures: &[FailureRecord]) -> Result<()> {
+    let content = serde_json::to_string_pretty(failures).map_err(|e| Error::json(path, e))?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// The invoice numbers of the recorded failures, limited to `categories` unless it is empty.
+pub fn failed_invoice_numbers(path: &str, categories: &[ErrorCategory]) -> Result<HashSet<String>> {
+    Ok(read_failures(path)?.into_iter()
+        .filter(|failure| categories.is_empty() || categories.contains(&failure.category))
+        .map(|failure| failure.invoice_number)
+        .collect())
+}
+
+/// Merges the outcome of a run into the failures file.
+/// Uploaded invoices are removed, failed ones are added or have their attempts counted up.
+pub fn update_failures(path: &str, summary: &UploadSummary) -> Result<Vec<FailureRecord>> {
+    let mut failures = read_failures(path)?;
+    failures.retain(|failure| !summary.is_uploaded(&failure.invoice_number));
+
+    for failed in &summary.failed {
+        let mut record = FailureRecord::new(&failed.invoice_number, &failed.error);
+        if let Some(index) = failures.iter().position(|failure| failure.invoice_number == failed.invoice_number) {
+            record.attempts = failures.remove(index).attempts + 1;
+        }
+        failures.push(record);
+    }
+
+    write_failures(path, &failures)?;
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_failures_file_names_the_file() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("failed_invoices.json");
+        let path = path.to_str().expect("utf-8 path");
+        fs::write(path, "[{\"invoice_number\":").expect("write failures file");
+
+        match read_failures(path) {
+            Err(Error::Json { path: failed, .. }) => assert_eq!(failed, path),
+            other => panic!("expected a json error, got {:?}", other),
+        }
+        assert!(read_failures(&format!("{}.missing", path)).expect("missing file is no error").is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use chrono::NaiveDate;
+use regex::Regex;
+use crate::invoice::invoice::InvoiceCSV;
+use crate::settings::Config;
+
+/// Selects which invoices of the csv are processed. Empty criteria match every invoice.
+#[derive(Debug, Default, Clone)]
+pub struct InvoiceFilter {
+    pub invoice_date_from: Option<NaiveDate>,
+    pub invoice_date_to: Option<NaiveDate>,
+    pub delivery_date_from: Option<NaiveDate>,
+    pub delivery_date_to: Option<NaiveDate>,
+    pub prefixes: Vec<String>,
+    pub transaction_types: Vec<String>,
+    /// billing addresses or the lexoffice contact ids mapped to them
+    pub customers: Vec<String>,
+    pub invoice_numbers: Vec<String>,
+    pub invoice_number_pattern: Option<Regex>,
+    /// upload at most this many invoices per run, counted after the uploaded ones were skipped
+    pub limit: Option<usize>,
+}
+
+impl InvoiceFilter {
+    /// Checks every criterion except `limit`, the config resolves customers to contact ids.
+    pub fn matches(&self, invoice: &InvoiceCSV, config: &Config) -> bool {
+        in_range(invoice.invoice_date(), self.invoice_date_from, self.invoice_date_to)
+            && in_range(invoice.delivery_date(), self.delivery_date_from, self.delivery_date_to)
+            && (self.prefixes.is_empty() || self.prefixes.contains(&invoice.prefix()))
+            && (self.transaction_types.is_empty() || self.transaction_types.iter().any(|t| t == invoice.transaction_type()))
+            && (self.customers.is_empty() || self.matches_customer(invoice, config))
+            && (self.invoice_numbers.is_empty() || self.invoice_numbers.iter().any(|n| n == invoice.invoice_number()))
+            && self.invoice_number_pattern.as_ref().is_none_or(|pattern| pattern.is_match(invoice.invoice_number()))
+    }
+
+    fn matches_customer(&self, invoice: &InvoiceCSV, config: &Config) -> bool {
+        let address = invoice.billing_address();
+        let customer_id = config.customer_id(address);
+        self.customers.iter().any(|customer| customer == address || Some(customer.as_str()) == customer_id)
+    }
+
+    pub fn apply<'a>(&self, invoices: Vec<&'a InvoiceCSV>, config: &Config) -> Vec<&'a InvoiceCSV> {
+        invoices.into_iter().filter(|invoice| self.matches(invoice, config)).collect()
+    }
+}
+
+/// Keeps the first `limit` invoices. Applied after `InvoiceFilter::apply` and after the uploaded invoices were
+/// removed, so the limit counts only invoices that are actually uploaded.
+pub fn limit_invoices(invoices: &mut Vec<&InvoiceCSV>, limit: Option<usize>) {
+    if let Some(limit) = limit {
+        invoices.truncate(limit);
+    }
+}
+
+fn in_range(date: NaiveDate, from: Option<NaiveDate>, to: Option<NaiveDate>) -> bool {
+    from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoice::invoice::fixtures;
+    use crate::migration::CURRENT_VERSION;
+
+    const SHOP: &str = "Shop GmbH, Hauptstr. 1, 10115 Berlin, Deutschland";
+    const TRADER: &str = "Trader BV, Keizersgracht 1, 1015 Amsterdam, Netherlands";
+    const SHOP_ID: &str = "6f4b2c1a-0a4e-4c56-9d2e-2b1f3a6c7d8e";
+
+    fn config() -> Config {
+        serde_yaml::from_str(&format!("version: {}\nprefixes: []\ncustomers:\n  - customer_id: {}\n    customer_adress: \"{}\"\n",
+                                      CURRENT_VERSION, SHOP_ID, SHOP)).expect("valid config")
+    }
+
+    fn date(day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+    }
+
+    fn numbers(invoices: &[&InvoiceCSV]) -> Vec<String> {
+        invoices.iter().map(|invoice| invoice.invoice_number().to_string()).collect()
+    }
+
+    #[test]
+    fn date_bounds_are_inclusive() {
+        // invoice date 15.01.2024, delivered on 10.01.2024
+        let invoice = fixtures::simple("AB-1", "b2c", SHOP);
+        let config = config();
+        let by_invoice_date = |from, to| InvoiceFilter { invoice_date_from: date(from), invoice_date_to: date(to), ..Default::default() };
+        let by_delivery_date = |from, to| InvoiceFilter { delivery_date_from: date(from), delivery_date_to: date(to), ..Default::default() };
+
+        assert!(by_invoice_date(15, 15).matches(&invoice, &config));
+        assert!(!by_invoice_date(16, 31).matches(&invoice, &config));
+        assert!(!by_invoice_date(1, 14).matches(&invoice, &config));
+        assert!(by_delivery_date(10, 10).matches(&invoice, &config));
+        assert!(!by_delivery_date(11, 31).matches(&invoice, &config));
+        assert!(!by_delivery_date(1, 9).matches(&invoice, &config));
+    }
+
+    #[test]
+    fn customers_match_by_address_or_contact_id() {
+        let config = config();
+        let shop = fixtures::simple("AB-1", "b2c", SHOP);
+        let trader = fixtures::simple("AB-2", "b2b", TRADER);
+        let by_customer = |customer: &str| InvoiceFilter { customers: vec![customer.to_string()], ..Default::default() };
+
+        assert!(by_customer(SHOP).matches(&shop, &config));
+        assert!(by_customer(SHOP_ID).matches(&shop, &config));
+        assert!(!by_customer(SHOP_ID).matches(&trader, &config));
+        assert!(by_customer(TRADER).matches(&trader, &config));
+        assert!(!by_customer("Trader BV").matches(&trader, &config));
+    }
+
+    #[test]
+    fn all_criteria_have_to_match() {
+        let config = config();
+        let invoices = [
+            fixtures::simple("AB-100", "b2c", SHOP),
+            fixtures::simple("AB-101", "b2b", SHOP),
+            fixtures::simple("AB-200", "b2c", SHOP),
+            fixtures::simple("CD-100", "b2c", SHOP),
+        ];
+        let filter = InvoiceFilter {
+            prefixes: vec!["AB".to_string()],
+            transaction_types: vec!["b2c".to_string()],
+            invoice_number_pattern: Some(Regex::new(r"-1\d\d$").expect("valid regex")),
+            ..Default::default()
+        };
+        assert_eq!(numbers(&filter.apply(invoices.iter().collect(), &config)), vec!["AB-100"]);
+    }
+
+    #[test]
+    fn limit_applies_to_the_selected_invoices() {
+        let config = config();
+        let invoices = ["AB-1", "CD-1", "AB-2", "AB-3"].map(|number| fixtures::simple(number, "b2c", SHOP));
+        let filter = InvoiceFilter { prefixes: vec!["AB".to_string()], limit: Some(2), ..Default::default() };
+
+        let mut selected = filter.apply(invoices.iter().collect(), &config);
+        assert_eq!(selected.len(), 3, "apply does not limit");
+        limit_invoices(&mut selected, filter.limit);
+        assert_eq!(numbers(&selected), vec!["AB-1", "AB-2"]);
+
+        limit_invoices(&mut selected, None);
+        assert_eq!(selected.len(), 2);
+    }
+}
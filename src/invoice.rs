@@ -1,22 +1,18 @@
-const BASE_URL: &'static str = "https://api.lexoffice.io/v1/";
-
+#[allow(clippy::module_inception)]
 pub mod invoice {
-    use std::path::Path;
     use chrono::{NaiveDate};
-    use log::{debug, error, info};
+    use log::error;
     use rust_decimal::Decimal;
     use serde::{Deserialize, Serialize};
-    use crate::invoice::{german_date_format, BASE_URL};
+    use sha2::{Digest, Sha256};
+    use crate::invoice::german_date_format;
+    use crate::invoice::german_optional_date_format;
     use crate::invoice::german_decimal_format;
+    use crate::error::{Error, Result};
+    use crate::logging;
     use crate::settings::{Config};
-    use std::error;
-    use reqwest::Client;
-    use async_recursion::async_recursion;
-    use reqwest::{multipart, Body};
-    use tokio::fs::File;
-    use tokio_util::codec::{BytesCodec, FramedRead};
-
-    type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+    use crate::address::{self, BillingAddress};
+    use crate::tax;
     #[derive(Debug, Serialize, Deserialize)]
     pub struct InvoiceCSV {
         #[serde(rename = "Rechnungsnummer")]
@@ -39,6 +35,39 @@ pub mod invoice {
         transaction_type: String,
         #[serde(rename = "Rechnungsadresse")]
         billing_adress: String,
+        /// optional column, takes precedence over the payment terms in the config
+        #[serde(rename = "Fälligkeitsdatum", default, with = "german_optional_date_format",
+                skip_serializing_if = "Option::is_none")]
+        due_date: Option<NaiveDate>,
+        /// optional column, the VAT id of business customers
+        #[serde(rename = "USt-IdNr.", default, skip_serializing_if = "Option::is_none")]
+        vat_id: Option<String>,
+    }
+
+    /// The columns covered by `InvoiceCSV::content_hash`, serialized like `InvoiceCSV`. Optional columns added
+    /// later stay out so the hashes stored in the state database remain comparable.
+    #[derive(Serialize)]
+    struct HashedColumns<'a> {
+        #[serde(rename = "Rechnungsnummer")]
+        invoice_number: &'a str,
+        #[serde(rename = "Interne Referenz")]
+        internal_reference: Option<&'a str>,
+        #[serde(rename = "Rechnungsdatum", with = "german_date_format")]
+        invoice_date: NaiveDate,
+        #[serde(rename = "Lieferdatum", with = "german_date_format")]
+        delivery_date: NaiveDate,
+        #[serde(rename = "Netto", with = "german_decimal_format")]
+        net: Decimal,
+        #[serde(rename = "USt. Rate (%)", with = "german_decimal_format")]
+        vat: Decimal,
+        #[serde(rename = "Endbetrag", with = "german_decimal_format")]
+        final_amount: Decimal,
+        #[serde(rename = "Währung")]
+        currency: &'a str,
+        #[serde(rename = "Transaktionstyp")]
+        transaction_type: &'a str,
+        #[serde(rename = "Rechnungsadresse")]
+        billing_adress: &'a str,
     }
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -48,55 +77,48 @@ pub mod invoice {
     }
 
     #[derive(Debug, Serialize, Deserialize)]
-    struct VoucherItem {
-        amount: Decimal,
+    pub struct VoucherItem {
+        pub amount: Decimal,
         #[serde(rename = "taxAmount")]
-        tax_amount: Decimal,
+        pub tax_amount: Decimal,
         #[serde(rename = "taxRatePercent")]
-        tax_rate_percent: Decimal,
-        // Innergemeinschaftliche Lieferung
+        pub tax_rate_percent: Decimal,
+        // category of the tax case unless configured otherwise, see `tax::treatment`
         #[serde(rename = "categoryId")]
-        category_id: String
+        pub category_id: String
     }
 
+    /// Body of `POST vouchers`, built from an invoice with `InvoiceCSV::build_voucher`.
     #[derive(Debug, Serialize, Deserialize)]
-    struct VoucherCreateRequest {
+    pub struct VoucherCreateRequest {
         #[serde(rename = "type")]
-        type_of_voucher: String,
+        pub type_of_voucher: String,
         #[serde(rename = "voucherNumber")]
-        voucher_number: String,
+        pub voucher_number: String,
         #[serde(rename = "voucherDate")]
-        voucher_date: String,
+        pub voucher_date: String,
         #[serde(rename = "shippingDate")]
-        shipping_date: Option<String>,
+        pub shipping_date: Option<String>,
         #[serde(rename = "dueDate")]
-        due_date: Option<String>,
+        pub due_date: Option<String>,
         #[serde(rename = "totalGrossAmount")]
-        total_gross_amount: Decimal,
+        pub total_gross_amount: Decimal,
         #[serde(rename = "totalTaxAmount")]
-        total_tax_amount: Decimal,
+        pub total_tax_amount: Decimal,
         // if b2b then net otherwise gross
         #[serde(rename = "taxType")]
-        tax_type: String,
+        pub tax_type: String,
         #[serde(rename = "contactId")]
-        contact_id: String,
+        pub contact_id: String,
         #[serde(rename = "voucherItems")]
-        voucher_items: Vec<VoucherItem>,
-    }
-
-    #[derive(Deserialize, Debug)]
-    struct VoucherCreationResponse {
-        id: String,
-        #[serde(rename = "resourceUri")]
-        #[allow(dead_code)]
-        resource_uri: String,
-    }
-
-    #[derive(Serialize, Deserialize, Debug)]
-    struct LexofficeError {
-        message: String,
+        pub voucher_items: Vec<VoucherItem>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub remark: Option<String>,
     }
 
+    /// Placeholders of the `remark` template, each written as `{name}`.
+    pub const REMARK_PLACEHOLDERS: &[&str] = &["invoice_number", "internal_reference", "prefix", "transaction_type",
+        "currency", "net", "vat_rate", "gross", "invoice_date", "delivery_date", "billing_address"];
 
     impl CompletedInvoices {
 
@@ -117,167 +139,282 @@ pub mod invoice {
         }
 
         pub fn validate(&self) -> bool {
-            if self.currency != "EUR" {
-                return false;
-            }
-            return true;
+            self.currency == "EUR"
         }
         pub fn get_invoice_date_formatted(&self) -> String {
             self.invoice_date.format("%Y-%m-%d").to_string()
         }
-        fn get_invoice_prefix(&self, config: &mut Config) -> Result<String> {
+        /// The part of the invoice number before the first -, selects folder and profile.
+        /// Invoice numbers without a - are used as prefix as a whole.
+        pub fn prefix(&self) -> String {
             // get the string before -
             let invoice_num = self.get_invoice_number();
-            let prefix_string = invoice_num.split("-").next().expect("No prefix found. The invoice number must have a prefix separated by a -");
-            Ok(config.get_path(&prefix_string).expect("Error while getting prefix"))
-
+            invoice_num.split('-').next().unwrap_or_default().to_string()
+        }
+        fn get_invoice_prefix(&self, config: &mut Config) -> Result<String> {
+            config.get_path(&self.prefix())
         }
         fn get_shipping_date_formatted(&self) -> String {
             self.delivery_date.format("%Y-%m-%d").to_string()
         }
 
-        #[async_recursion]
-        pub async fn upload(&self, settings: &mut Config) -> Result<()>{
-            let file_path = format!("{}/{}/{}.pdf", self.get_invoice_prefix(settings)?, self.delivery_date.format("%m-%Y"), self.get_invoice_number());
-            let client = Client::new();
-            // check if the file exists
-            if !Path::new(&file_path).exists() {
-                error!("File {} does not exist!", file_path);
-                return Err("File does not exist!".into());
-            }
+        /// Path of the invoice pdf: `<prefix folder>/<delivery month>/<invoice number>.pdf`.
+        pub fn file_path(&self, settings: &mut Config) -> Result<String> {
+            Ok(self.file_path_in(&self.get_invoice_prefix(settings)?))
+        }
 
-            // check that the file is not empty and is smaller than 5 MB (limitation of lexofffice)
-            let metadata = std::fs::metadata(&file_path)?;
-            if metadata.len() == 0 || metadata.len() > 5_000_000 {
-                error!("File {} is empty or too large!", file_path);
-                return Err("File is empty or too large!".into());
-            }
+        /// Path of the invoice pdf if its prefix is mapped, never asks for a folder.
+        pub fn mapped_file_path(&self, settings: &Config) -> Option<String> {
+            settings.prefix_path(&self.prefix()).map(|folder| self.file_path_in(folder))
+        }
+
+        fn file_path_in(&self, folder: &str) -> String {
+            format!("{}/{}/{}.pdf", folder, self.delivery_date.format("%m-%Y"), self.get_invoice_number())
+        }
 
-            // construct the upload request
-            let upload_req = VoucherCreateRequest{
+        /// Builds the voucher for lexoffice, unknown addresses are resolved through the config.
+        pub fn build_voucher(&self, settings: &mut Config) -> Result<VoucherCreateRequest> {
+            let tax = tax::treatment(self, settings)?;
+            let tax_amount = self.final_amount - self.net;
+            Ok(VoucherCreateRequest{
                 type_of_voucher: "salesinvoice".to_string(),
                 voucher_number: self.invoice_number.clone(),
                 voucher_date: self.get_invoice_date_formatted(),
                 shipping_date: Some(self.get_shipping_date_formatted()),
-                due_date: None,
+                due_date: self.due_date(settings)?.map(|date| date.format("%Y-%m-%d").to_string()),
                 total_gross_amount: self.final_amount,
-                total_tax_amount: self.net-self.final_amount,
-                tax_type: if self.transaction_type == "b2b".to_string() { "net".to_string() } else { "gross".to_string() },
+                total_tax_amount: tax_amount,
                 contact_id: settings.get_customer_id(&self.billing_adress)?,
                 voucher_items: vec![VoucherItem{
-                    amount: self.final_amount,
-                    tax_amount: self.net-self.final_amount,
+                    // the item amount is net or gross depending on the tax type
+                    amount: if tax.tax_type == "net" { self.net } else { self.final_amount },
+                    tax_amount,
                     tax_rate_percent: self.vat,
-                    category_id: "9075a4e3-66de-4795-a016-3889feca0d20".to_string(),
+                    category_id: tax.category_id,
                 }],
-            };
-            let res: reqwest::Response = client.post(format!("{}vouchers", BASE_URL))
-                .bearer_auth(&settings.api_key)
-                .json(&upload_req)
-                .send()
-                .await?;
-
-            if res.status() == 401 {
-                error!("API key is invalid! Please enter new one in the config file.");
-                let error_message: LexofficeError = res.json().await?;
-                error!("Error: {}", error_message.message);
-
-                settings.invalidate_api_key();
-                return self.upload(settings).await;
-            }
-            if res.status() != 200 {
-                error!("Error while uploading invoice {}, got status code {}!", self.invoice_number, res.status());
-                let error_message: LexofficeError = res.json().await?;
-                error!("Error: {}", error_message.message);
-                return Err("Error while uploading invoice!".into());
-            }
-
-            let result = res.json::<VoucherCreationResponse>().await?;
-            info!("Successfully created voucher with id {}", result.id);
-
-            debug!("Uploading file {} to voucher {}", file_path, result.id);
-
-            let url = format!("{}vouchers/{}/files", BASE_URL, result.id);
-
-
-            let file = File::open(&file_path).await?;
-
-            // read file body stream
-            let stream = FramedRead::new(file, BytesCodec::new());
-            let file_body = Body::wrap_stream(stream);
-
-            //make form part of file
-            let some_file = multipart::Part::stream(file_body)
-                .file_name("invoice.pdf")
-                .mime_str("application/pdf")?;
-
-            let form = multipart::Form::new()
-                .text("type", "voucher")
-                .part("file", some_file);
-
-            let upload_res = client.post(&url)
-                .bearer_auth(&settings.api_key)
-                .multipart(form)
-                .send()
-                .await?;
+                tax_type: tax.tax_type,
+                remark: settings.remark.as_deref().map(|template| self.remark(template)).filter(|remark| !remark.is_empty()),
+            })
+        }
 
-            if upload_res.status() != 202 {
-                error!("Error during file upload");
-                return Err("Error during file upload".into());
+        /// Fills the `REMARK_PLACEHOLDERS` of the template with the values of this row.
+        pub fn remark(&self, template: &str) -> String {
+            let mut remark = template.to_string();
+            for placeholder in REMARK_PLACEHOLDERS {
+                let pattern = format!("{{{}}}", placeholder);
+                if !remark.contains(&pattern) {
+                    continue;
+                }
+                let value = match *placeholder {
+                    "invoice_number" => self.invoice_number.clone(),
+                    "internal_reference" => self.internal_reference.clone().unwrap_or_default().trim().to_string(),
+                    "prefix" => self.prefix(),
+                    "transaction_type" => self.transaction_type.clone(),
+                    "currency" => self.currency.clone(),
+                    "net" => self.net.to_string(),
+                    "vat_rate" => self.vat.to_string(),
+                    "gross" => self.final_amount.to_string(),
+                    "invoice_date" => self.invoice_date.format("%d.%m.%Y").to_string(),
+                    "delivery_date" => self.delivery_date.format("%d.%m.%Y").to_string(),
+                    "billing_address" => self.billing_adress.clone(),
+                    _ => continue,
+                };
+                remark = remark.replace(&pattern, &value);
             }
+            remark.trim().to_string()
+        }
 
-            info!("Successfully uploaded file {} to voucher {}", file_path, result.id);
-
-            Ok(())
+        /// The due date column, or the invoice date plus the payment terms of the customer.
+        pub fn due_date(&self, settings: &Config) -> Result<Option<NaiveDate>> {
+            match (self.due_date, settings.payment_terms(&self.billing_adress)) {
+                (Some(due_date), _) => Ok(Some(due_date)),
+                (None, Some(terms)) => terms.due_date(self.invoice_date).map(Some),
+                (None, None) => Ok(None),
+            }
         }
+
         pub fn invoice_number(&self) -> &str {
             &self.invoice_number
         }
+        /// sha256 of the columns in `HashedColumns`, changes whenever one of them is corrected.
+        pub fn content_hash(&self) -> String {
+            let columns = HashedColumns {
+                invoice_number: &self.invoice_number,
+                internal_reference: self.internal_reference.as_deref(),
+                invoice_date: self.invoice_date,
+                delivery_date: self.delivery_date,
+                net: self.net,
+                vat: self.vat,
+                final_amount: self.final_amount,
+                currency: &self.currency,
+                transaction_type: &self.transaction_type,
+                billing_adress: &self.billing_adress,
+            };
+            let row = serde_json::to_string(&columns).unwrap_or_default();
+            format!("{:x}", Sha256::digest(row.as_bytes()))
+        }
+        pub fn internal_reference(&self) -> Option<&str> {
+            self.internal_reference.as_deref()
+        }
+        pub fn currency(&self) -> &str {
+            &self.currency
+        }
+        pub fn vat_rate(&self) -> Decimal {
+            self.vat
+        }
+        pub fn net(&self) -> Decimal {
+            self.net
+        }
+        pub fn final_amount(&self) -> Decimal {
+            self.final_amount
+        }
+        pub fn vat_id(&self) -> Option<&str> {
+            self.vat_id.as_deref().map(str::trim).filter(|vat_id| !vat_id.is_empty())
+        }
+        pub fn billing_address(&self) -> &str {
+            &self.billing_adress
+        }
+        /// The billing address split into name, street, postal code, city, region and country.
+        pub fn parsed_billing_address(&self) -> BillingAddress {
+            address::parse(&self.billing_adress)
+        }
+        pub fn invoice_date(&self) -> NaiveDate {
+            self.invoice_date
+        }
+        pub fn delivery_date(&self) -> NaiveDate {
+            self.delivery_date
+        }
+        pub fn transaction_type(&self) -> &str {
+            &self.transaction_type
+        }
     }
 
-    pub fn read_invoice_csv(path: String) -> Vec<InvoiceCSV>{
-        let mut rdr = csv::Reader::from_path(path).unwrap();
-        let mut invoices: Vec<InvoiceCSV> = Vec::new();
-        for result in rdr.deserialize() {
-            if result.is_err() {
-                error!("Error parsing invoice: {}", result.unwrap_err());
-                continue;
-            }
-            let record: InvoiceCSV = result.unwrap();
-            if record.validate() {
-                invoices.push(record);
-            }else {
+    /// The rows that can be uploaded, rows in other currencies than EUR are logged and left out.
+    pub fn read_invoice_csv(path: String) -> Result<Vec<InvoiceCSV>>{
+        let mut invoices = read_all_invoice_csv(path)?;
+        invoices.retain(|record| {
+            let valid = record.validate();
+            if !valid {
                 error!("invoice {} is not valid", record.invoice_number);
             }
+            valid
+        });
+        Ok(invoices)
+    }
+
+    /// Every row that can be parsed, whatever its currency, e.g. for reports.
+    pub fn read_all_invoice_csv(path: String) -> Result<Vec<InvoiceCSV>>{
+        let mut rdr = csv::Reader::from_path(&path).map_err(|e| Error::csv(&path, e))?;
+        let mut invoices: Vec<InvoiceCSV> = Vec::new();
+        for result in rdr.deserialize() {
+            let record: InvoiceCSV = match result {
+                Ok(record) => record,
+                Err(e) => {
+                    error!("Error parsing invoice: {}", e);
+                    continue;
+                }
+            };
+            logging::redact_address(&record.billing_adress);
+            invoices.push(record);
         }
-        invoices
+        Ok(invoices)
     }
 
-    fn read_done_invoice_csv(path: String) -> Vec<CompletedInvoices>{
-        let mut rdr = csv::Reader::from_path(path).unwrap();
+    fn read_done_invoice_csv(path: String) -> Result<Vec<CompletedInvoices>>{
+        let mut rdr = csv::Reader::from_path(&path).map_err(|e| Error::csv(&path, e))?;
         let mut invoices: Vec<CompletedInvoices> = Vec::new();
         for result in rdr.deserialize() {
-            let record: CompletedInvoices = result.unwrap();
+            let record: CompletedInvoices = result.map_err(|e| Error::csv(&path, e))?;
             invoices.push(record);
         }
-        invoices
+        Ok(invoices)
     }
 
-    pub fn try_read_done_invoices_csv(path: String) -> Vec<CompletedInvoices>{
+    pub fn try_read_done_invoices_csv(path: String) -> Result<Vec<CompletedInvoices>>{
         // verify if the file exists
         if std::path::Path::new(&path).exists() {
             return read_done_invoice_csv(path);
         }
 
-        return vec![];
+        Ok(vec![])
     }
 
-    pub fn write_done_invoice_csv(path: &String, invoices: &Vec<CompletedInvoices>) {
-        let mut wtr = csv::Writer::from_path(path).expect(&*format!("Could not open file {}", path));
+    pub fn write_done_invoice_csv(path: &String, invoices: &Vec<CompletedInvoices>) -> Result<()> {
+        let mut wtr = csv::Writer::from_path(path).map_err(|e| Error::csv(path, e))?;
         for invoice in invoices {
-            wtr.serialize(invoice).expect("Could not serialize written invoice")
+            wtr.serialize(invoice).map_err(|e| Error::csv(path, e))?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Invoices for unit tests, parsed from csv rows like the exports.
+    #[cfg(test)]
+    pub(crate) mod fixtures {
+        use super::InvoiceCSV;
+
+        pub const HEADER: &str = "Rechnungsnummer,Interne Referenz,Rechnungsdatum,Lieferdatum,Netto,USt. Rate (%),Endbetrag,\
+                                  Währung,Transaktionstyp,Rechnungsadresse,USt-IdNr.,Fälligkeitsdatum";
+
+        /// Parses one row in the column order of `HEADER`.
+        pub fn invoice(row: &str) -> InvoiceCSV {
+            let csv = format!("{}\n{}\n", HEADER, row);
+            csv::Reader::from_reader(csv.as_bytes()).deserialize().next().expect("one row").expect("valid row")
+        }
+
+        /// An invoice dated 15.01.2024 and delivered on 10.01.2024 with only the interesting columns set.
+        pub fn simple(number: &str, transaction_type: &str, address: &str) -> InvoiceCSV {
+            invoice(&format!("{},,15.01.2024,10.01.2024,\"100,00\",\"19,00\",\"119,00\",EUR,{},\"{}\",,",
+                             number, transaction_type, address))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::migration::CURRENT_VERSION;
+
+        const ADDRESS: &str = "Shop GmbH, Hauptstr. 1, 10115 Berlin, Deutschland";
+
+        fn config(terms: &str) -> Config {
+            serde_yaml::from_str(&format!("version: {}\nprefixes: []\npayment_terms: net 30\ncustomers:\n  \
+                                           - customer_id: c1\n    customer_adress: \"{}\"\n    payment_terms: {}\n",
+                                          CURRENT_VERSION, ADDRESS, terms)).expect("valid config")
+        }
+
+        fn date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+            NaiveDate::from_ymd_opt(year, month, day)
+        }
+
+        #[test]
+        fn due_date_column_wins_over_payment_terms() {
+            let config = config("net 14");
+            let with_column = fixtures::invoice(&format!(
+                "AB-1,,15.01.2024,10.01.2024,\"100,00\",\"19,00\",\"119,00\",EUR,b2c,\"{}\",,01.03.2024", ADDRESS));
+            assert_eq!(with_column.due_date(&config).expect("due date"), date(2024, 3, 1));
+
+            let without_column = fixtures::simple("AB-2", "b2c", ADDRESS);
+            assert_eq!(without_column.due_date(&config).expect("due date"), date(2024, 1, 29), "terms of the customer");
+            let other_customer = fixtures::simple("AB-3", "b2c", "Other GmbH, Weg 2, 20095 Hamburg");
+            assert_eq!(other_customer.due_date(&config).expect("due date"), date(2024, 2, 14), "terms of the config");
+        }
+
+        #[test]
+        fn remark_fills_every_occurrence_of_known_placeholders() {
+            let invoice = fixtures::invoice(&format!(
+                "AB-1,SHOP-7,15.01.2024,10.01.2024,\"100,00\",\"19,00\",\"119,00\",EUR,b2c,\"{}\",,", ADDRESS));
+            assert_eq!(invoice.remark("Order {internal_reference} ({internal_reference}) via {prefix} on {invoice_date}"),
+                       "Order SHOP-7 (SHOP-7) via SHOP on 15.01.2024");
+            assert_eq!(invoice.remark("{gross} {currency} incl. {vat_rate}%"), "119.00 EUR incl. 19.00%");
+        }
+
+        #[test]
+        fn remark_keeps_unknown_placeholders_and_trims_empty_values() {
+            let invoice = fixtures::simple("AB-1", "b2c", ADDRESS);
+            assert_eq!(invoice.remark("{order_id} {invoice_number} {}"), "{order_id} AB-1 {}");
+            assert_eq!(invoice.remark(" {internal_reference} "), "");
+            assert_eq!(invoice.remark("no placeholders"), "no placeholders");
         }
-        wtr.flush().expect("Could not flush written invoice");
     }
 }
 
@@ -285,7 +422,7 @@ mod german_date_format {
     use chrono::{ NaiveDate};
     use serde::{self, Deserialize, Serializer, Deserializer};
 
-    const FORMAT: &'static str = "%d.%m.%Y";
+    const FORMAT: &str = "%d.%m.%Y";
 
     // The signature of a serialize_with function must follow the pattern:
     //
@@ -319,7 +456,42 @@ mod german_date_format {
             D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        NaiveDate::parse_from_str(&*s, FORMAT).map_err(serde::de::Error::custom)
+        NaiveDate::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Like `german_date_format`, an empty cell is no date.
+mod german_optional_date_format {
+    use chrono::NaiveDate;
+    use serde::{self, Deserialize, Serializer, Deserializer};
+
+    const FORMAT: &str = "%d.%m.%Y";
+
+    pub fn serialize<S>(
+        date: &Option<NaiveDate>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        match date {
+            Some(date) => serializer.serialize_str(&date.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<NaiveDate>, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) if !s.trim().is_empty() => NaiveDate::parse_from_str(s.trim(), FORMAT)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+            _ => Ok(None),
+        }
     }
 }
 
@@ -336,7 +508,7 @@ mod german_decimal_format {
             S: Serializer,
     {
         let s = decimal.to_string();
-        serializer.serialize_str(&*s)
+        serializer.serialize_str(&s)
     }
 
     pub fn deserialize<'de, D>(
@@ -345,8 +517,8 @@ mod german_decimal_format {
         where
             D: Deserializer<'de>,
     {
-        let s = String::deserialize(deserializer)?.replace(",", ".");
-        Decimal::from_str(&*s).map_err(serde::de::Error::custom)
+        let s = String::deserialize(deserializer)?.replace(',', ".");
+        Decimal::from_str(&s).map_err(serde::de::Error::custom)
     }
 }
 
@@ -0,0 +1,275 @@
+use std::time::Duration;
+use log::{error, info, warn};
+use reqwest::{Client, Response, StatusCode};
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::error::{Error, Result, ValidationDetail};
+use crate::invoice::invoice::{VoucherCreateRequest, VoucherItem};
+use crate::settings::Config;
+
+pub const BASE_URL: &str = "https://api.lexoffice.io/v1/";
+/// How often a read is repeated after lexoffice answered with 429.
+const RATE_LIMIT_RETRIES: u32 = 3;
+
+
+#[derive(Deserialize, Debug)]
+pub struct VoucherCreationResponse {
+    pub id: String,
+    #[serde(rename = "resourceUri")]
+    pub resource_uri: String,
+}
+
+/// A voucher as returned by `GET vouchers/{id}`, dates are full timestamps like `2023-01-15T00:00:00.000+01:00`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Voucher {
+    pub id: String,
+    /// optimistic locking, an update has to send the version it is based on
+    pub version: i64,
+    pub voucher_status: Option<String>,
+    pub voucher_number: Option<String>,
+    pub voucher_date: Option<String>,
+    pub shipping_date: Option<String>,
+    pub due_date: Option<String>,
+    pub total_gross_amount: Option<Decimal>,
+    pub total_tax_amount: Option<Decimal>,
+    pub tax_type: Option<String>,
+    pub contact_id: Option<String>,
+    pub remark: Option<String>,
+    #[serde(default)]
+    pub voucher_items: Vec<VoucherItem>,
+    /// ids of the attached files
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+/// Result of `GET vouchers?voucherNumber=`, only the ids are needed.
+#[derive(Deserialize, Debug)]
+struct VoucherPage {
+    #[serde(default)]
+    content: Vec<VoucherReference>,
+}
+
+#[derive(Deserialize, Debug)]
+struct VoucherReference {
+    id: String,
+}
+
+/// Payment information of a voucher as returned by `GET payments/{id}`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Payment {
+    pub open_amount: Option<Decimal>,
+    /// `balanced`, `openRevenue` or `openExpense`
+    pub payment_status: Option<String>,
+    pub currency: Option<String>,
+    /// e.g. `open`, `paid`, `paidoff` or `voided`
+    pub voucher_status: Option<String>,
+    pub paid_date: Option<String>,
+    #[serde(default)]
+    pub payment_items: Vec<PaymentItem>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentItem {
+    pub payment_item_type: Option<String>,
+    pub posting_date: Option<String>,
+    pub amount: Option<Decimal>,
+    pub currency: Option<String>,
+}
+
+#[derive(Serialize)]
+struct VoucherUpdate<'a> {
+    #[serde(flatten)]
+    voucher: &'a VoucherCreateRequest,
+    version: i64,
+}
+
+/// Error body of lexoffice. Older endpoints like `vouchers` report an `IssueList`,
+/// newer ones a `message` with `details`.
+#[derive(Deserialize, Debug)]
+pub struct LexofficeError {
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub details: Vec<LexofficeErrorDetail>,
+    #[serde(default, rename = "IssueList")]
+    pub issue_list: Vec<LexofficeIssue>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LexofficeErrorDetail {
+    pub violation: Option<String>,
+    pub field: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LexofficeIssue {
+    #[serde(rename = "i18nKey")]
+    pub i18n_key: Option<String>,
+    pub source: Option<String>,
+    #[serde(rename = "type")]
+    pub issue_type: Option<String>,
+}
+
+impl LexofficeError {
+    fn validation_details(&self) -> Vec<ValidationDetail> {
+        let details = self.details.iter().map(|detail| ValidationDetail {
+            field: detail.field.clone(),
+            violation: detail.violation.clone(),
+            message: detail.message.clone(),
+        });
+        let issues = self.issue_list.iter().map(|issue| ValidationDetail {
+            field: issue.source.clone(),
+            violation: issue.i18n_key.clone(),
+            message: issue.issue_type.clone(),
+        });
+        details.chain(issues).collect()
+    }
+
+    fn summary(&self) -> Option<String> {
+        self.message.clone().or_else(|| self.error.clone())
+    }
+}
+
+/// Turns a failed response into an `Error`, keeping the status code even if the body is unreadable.
+pub async fn error_from_response(res: Response) -> Error {
+    let status = res.status();
+    let retry_after = res.headers().get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let body = res.text().await.unwrap_or_default();
+    // the body is only a bonus, an unexpected format must not hide the status code
+    let (message, details) = match serde_json::from_str::<LexofficeError>(&body) {
+        Ok(error) => (
+            error.summary().unwrap_or_else(|| status.canonical_reason().unwrap_or_default().to_string()),
+            error.validation_details(),
+        ),
+        Err(_) if body.trim().is_empty() => (status.canonical_reason().unwrap_or_default().to_string(), vec![]),
+        Err(_) => (body.trim().to_string(), vec![]),
+    };
+
+    match status {
+        StatusCode::UNAUTHORIZED => Error::ApiAuth(message),
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimit { retry_after },
+        StatusCode::BAD_REQUEST | StatusCode::NOT_ACCEPTABLE | StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY =>
+            Error::ApiValidation { status: status.as_u16(), message, details },
+        _ => Error::Api { status: status.as_u16(), message },
+    }
+}
+
+/// Fetches a voucher, e.g. to compare it with the csv row or to get its current version.
+pub async fn get_voucher(client: &Client, config: &Config, voucher_id: &str) -> Result<Voucher> {
+    get_json(client, config, &format!("vouchers/{}", voucher_id), &[]).await
+}
+
+/// Ids of the vouchers with this number, for invoices whose voucher id was never recorded.
+pub async fn find_voucher_ids(client: &Client, config: &Config, voucher_number: &str) -> Result<Vec<String>> {
+    let page: VoucherPage = get_json(client, config, "vouchers", &[("voucherNumber", voucher_number)]).await?;
+    Ok(page.content.into_iter().map(|voucher| voucher.id).collect())
+}
+
+/// Fetches the payment information of a voucher.
+pub async fn get_payment(client: &Client, config: &Config, voucher_id: &str) -> Result<Payment> {
+    get_json(client, config, &format!("payments/{}", voucher_id), &[]).await
+}
+
+/// Reads a resource, waits and tries again when lexoffice reports the rate limit.
+async fn get_json<T: DeserializeOwned>(client: &Client, config: &Config, path: &str, query: &[(&str, &str)]) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        let res = client.get(format!("{}{}", BASE_URL, path))
+            .query(query)
+            .bearer_auth(config.api_key().expose())
+            .send()
+            .await?;
+
+        if res.status().is_success() {
+            return Ok(res.json::<T>().await?);
+        }
+        match error_from_response(res).await {
+            Error::RateLimit { retry_after } if attempt < RATE_LIMIT_RETRIES => {
+                attempt += 1;
+                let wait = retry_after.unwrap_or(1);
+                warn!("Rate limit of lexoffice reached, waiting {}s", wait);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+            }
+            error => return Err(error),
+        }
+    }
+}
+
+/// Replaces the voucher with `request`. lexoffice rejects the update if `version` is outdated
+/// or the voucher can no longer be changed, e.g. because it is booked.
+pub async fn update_voucher(client: &Client, config: &Config, voucher_id: &str, request: &VoucherCreateRequest,
+                            version: i64) -> Result<()> {
+    let res = client.put(format!("{}vouchers/{}", BASE_URL, voucher_id))
+        .bearer_auth(config.api_key().expose())
+        .json(&VoucherUpdate { voucher: request, version })
+        .send()
+        .await?;
+
+    if !res.status().is_success() {
+        return Err(error_from_response(res).await);
+    }
+    Ok(())
+}
+
+
+/// Subset of the lexoffice profile the api key belongs to.
+#[derive(Deserialize, Debug)]
+pub struct Profile {
+    #[serde(rename = "organizationId")]
+    pub organization_id: String,
+    #[serde(rename = "companyName")]
+    pub company_name: String,
+    #[serde(rename = "taxType")]
+    pub tax_type: Option<String>,
+    #[serde(rename = "smallBusiness")]
+    pub small_business: Option<bool>,
+}
+
+/// Fetches the profile of the organization the api key belongs to.
+/// Returns `Ok(None)` if lexoffice rejected the api key.
+pub async fn get_profile(client: &Client, config: &Config) -> Result<Option<Profile>> {
+    let res = client.get(format!("{}profile", BASE_URL))
+        .bearer_auth(config.api_key().expose())
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::UNAUTHORIZED {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(error_from_response(res).await);
+    }
+    Ok(Some(res.json::<Profile>().await?))
+}
+
+/// Checks the api key against lexoffice before any invoice is touched.
+/// Asks for a new key on rejection as long as the config allows it.
+pub async fn check_api_key(config: &mut Config) -> Result<Profile> {
+    let client = Client::new();
+    loop {
+        if let Some(profile) = get_profile(&client, config).await? {
+            info!("Api key {} belongs to {} ({})", config.api_key().masked(), profile.company_name, profile.organization_id);
+            info!("Tax type: {}, small business: {}",
+                  profile.tax_type.as_deref().unwrap_or("unknown"),
+                  profile.small_business.map_or("unknown".to_string(), |b| b.to_string()));
+            if profile.small_business == Some(true) {
+                warn!("The organization is registered as small business, vouchers with VAT may be rejected");
+            }
+            return Ok(profile);
+        }
+
+        error!("Api key {} was rejected by lexoffice!", config.api_key().masked());
+        if !config.invalidate_api_key() {
+            return Err(Error::ApiAuth("The api key was rejected by lexoffice".to_string()));
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Uploads sales invoices from a csv export together with their pdf files to lexoffice.
+//!
+//! The command line tool is a thin layer over this crate, other programs can use
+//! [`InvoiceCSV`] to parse exports, [`Config`] for the mappings and [`Uploader`] to upload.
+
+pub mod error;
+pub mod settings;
+pub mod invoice;
+pub mod secrets;
+pub mod lexoffice;
+pub mod migration;
+pub mod config_check;
+pub mod mapping;
+pub mod uploader;
+pub mod failures;
+pub mod report;
+pub mod filter;
+pub mod logging;
+pub mod state;
+pub mod changes;
+pub mod verify;
+pub mod payments;
+pub mod countries;
+pub mod address;
+pub mod tax;
+pub mod vat_rates;
+pub mod revenue;
+
+pub use error::{Error, ErrorCategory, Result};
+pub use invoice::invoice::{read_all_invoice_csv, read_invoice_csv, CompletedInvoices, InvoiceCSV, VoucherCreateRequest, VoucherItem};
+pub use settings::{load_settings, Config, Profile};
+pub use uploader::{FailedInvoice, UploadEvent, UploadSummary, UploadedInvoice, UploadedVoucher, Uploader};
@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use chrono::{DateTime, Local, NaiveDate};
+use log::{LevelFilter, Record};
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::trigger::Trigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::{LogFile, RollingFileAppender};
+use log4rs::config::{Appender, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::{Encode, Write};
+use log4rs::filter::threshold::ThresholdFilter;
+use crate::error::{Error, Result};
+
+const FILE_PATTERN: &str = "{d(%Y-%m-%d %H:%M:%S%.3f)} {l} {t} - {m}{n}";
+const CONSOLE_PATTERN: &str = "{d(%H:%M:%S)} {l} - {m}{n}";
+const LOG_FILE: &str = "output.log";
+
+/// Texts that are replaced by `REDACTED` in every log line.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+static REDACT_ADDRESSES: AtomicBool = AtomicBool::new(false);
+const REDACTED: &str = "[redacted]";
+
+/// When the log file is moved aside and a new one is started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// once the file exceeds the given number of bytes
+    Size(u64),
+    /// on the first message of a new day
+    Daily,
+    Never,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    pub level: LevelFilter,
+    pub dir: PathBuf,
+    pub rotation: Rotation,
+    /// number of rotated files that are kept
+    pub keep: u32,
+    /// write the log file as one json object per line
+    pub json: bool,
+    /// hide billing addresses in addition to the api key
+    pub redact_addresses: bool,
+    /// only warnings and errors on the console, e.g. while a progress bar is shown
+    pub quiet_console: bool,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            dir: PathBuf::from("log"),
+            rotation: Rotation::Size(10_000_000),
+            keep: 7,
+            json: false,
+            redact_addresses: false,
+            quiet_console: false,
+        }
+    }
+}
+
+/// Sets up console and file logging. If the log directory cannot be created only the console is used.
+pub fn init(settings: &LogSettings) -> Result<()> {
+    REDACT_ADDRESSES.store(settings.redact_addresses, Ordering::Relaxed);
+
+    let console = ConsoleAppender::builder()
+        .encoder(Box::new(Redacting(Box::new(PatternEncoder::new(CONSOLE_PATTERN)))))
+        .build();
+    let mut console_appender = Appender::builder();
+    if settings.quiet_console {
+        console_appender = console_appender.filter(Box::new(ThresholdFilter::new(LevelFilter::Warn)));
+    }
+    let mut builder = log4rs::config::Config::builder()
+        .appender(console_appender.build("console", Box::new(console)));
+    let mut root = Root::builder().appender("console");
+
+    let file = fs::create_dir_all(&settings.dir)
+        .map_err(Error::from)
+        .and_then(|_| file_appender(settings));
+    let file_error = match file {
+        Ok(file) => {
+            builder = builder.appender(Appender::builder().build("logfile", Box::new(file)));
+            root = root.appender("logfile");
+            None
+        }
+        Err(e) => Some(e),
+    };
+
+    let config = builder.build(root.build(settings.level))
+        .map_err(|e| Error::config(format!("Invalid logging setup: {}", e)))?;
+    log4rs::init_config(config).map_err(|e| Error::config(e.to_string()))?;
+
+    if let Some(e) = file_error {
+        log::warn!("Logging to the console only, could not set up {}: {}", settings.dir.display(), e);
+    }
+    Ok(())
+}
+
+fn file_appender(settings: &LogSettings) -> Result<RollingFileAppender> {
+    let encoder: Box<dyn Encode> = if settings.json {
+        Box::new(JsonEncoder::new())
+    } else {
+        Box::new(PatternEncoder::new(FILE_PATTERN))
+    };
+    let path = settings.dir.join(LOG_FILE);
+    let trigger: Box<dyn Trigger> = match settings.rotation {
+        Rotation::Size(limit) => Box::new(SizeTrigger::new(limit)),
+        Rotation::Daily => Box::new(DailyTrigger::new(&path)),
+        Rotation::Never => Box::new(SizeTrigger::new(u64::MAX)),
+    };
+    let archive = settings.dir.join("output.{}.log");
+    let roller = FixedWindowRoller::builder()
+        .build(&archive.to_string_lossy(), settings.keep.max(1))
+        .map_err(|e| Error::config(format!("Invalid log rotation: {}", e)))?;
+
+    RollingFileAppender::builder()
+        .encoder(Box::new(Redacting(encoder)))
+        .build(path, Box::new(CompoundPolicy::new(trigger, Box::new(roller))))
+        .map_err(Error::from)
+}
+
+/// Hides `secret` in all following log lines.
+pub fn redact(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap_or_else(|e| e.into_inner());
+    if !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_string());
+        // replace longer texts first so an address containing another one is hidden completely
+        secrets.sort_by_key(|known| std::cmp::Reverse(known.len()));
+    }
+}
+
+/// Hides the address in all following log lines if address redaction is enabled.
+pub fn redact_address(address: &str) {
+    if REDACT_ADDRESSES.load(Ordering::Relaxed) {
+        redact(address);
+    }
+}
+
+fn redacted(message: String) -> String {
+    let secrets = SECRETS.read().unwrap_or_else(|e| e.into_inner());
+    secrets.iter().fold(message, |message, secret| message.replace(secret.as_str(), REDACTED))
+}
+
+/// Wraps an encoder and removes the registered secrets from the message.
+#[derive(Debug)]
+struct Redacting(Box<dyn Encode>);
+
+impl Encode for Redacting {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        let message = redacted(record.args().to_string());
+        self.0.encode(w, &Record::builder()
+            .args(format_args!("{}", message))
+            .level(record.level())
+            .target(record.target())
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .build())
+    }
+}
+
+/// Rolls the log file on the first message after midnight.
+#[derive(Debug)]
+struct DailyTrigger {
+    day: Mutex<NaiveDate>,
+}
+
+impl DailyTrigger {
+    fn new(path: &std::path::Path) -> Self {
+        let day = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| DateTime::<Local>::from(modified).date_naive())
+            .unwrap_or_else(|_| Local::now().date_naive());
+        Self { day: Mutex::new(day) }
+    }
+}
+
+impl Trigger for DailyTrigger {
+    fn trigger(&self, _file: &LogFile) -> anyhow::Result<bool> {
+        let today = Local::now().date_naive();
+        let mut day = self.day.lock().unwrap_or_else(|e| e.into_inner());
+        if *day == today {
+            return Ok(false);
+        }
+        *day = today;
+        Ok(true)
+    }
+}
@@ -1,91 +1,609 @@
+use std::path::Path;
 use std::process::exit;
-use log::{debug, error, info, LevelFilter};
-use log4rs;
-use crate::invoice::invoice::{CompletedInvoices};
-use log4rs::append::console::ConsoleAppender;
-use log4rs::append::file::FileAppender;
-use log4rs::encode::pattern::PatternEncoder;
-use log4rs::config::{Appender, Config, Root};
+use std::time::Duration;
+use clap::Parser;
+use log::{error, info, warn};
+use cli_lexuploader::{config_check, failures, logging, mapping, read_all_invoice_csv, read_invoice_csv, settings, Config, Error,
+                      ErrorCategory, InvoiceCSV, UploadSummary, Uploader};
+use cli_lexuploader::changes::{self, ChangedInvoice};
+use cli_lexuploader::{filter, payments, revenue, tax};
+use cli_lexuploader::report::RunReport;
+use cli_lexuploader::state::StateStore;
+use cli_lexuploader::verify::{self, VerifyStatus};
 
-mod settings;
-mod invoice;
+mod cli;
+mod daemon;
+mod progress;
+mod watch;
+
+use progress::{Progress, ProgressMode};
+
+const INVOICE_CSV: &str = "invoices.csv";
 
 #[tokio::main]
 async fn main() {
-    // set up logging
-    let logfile = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{l} - {m}\n")))
-        .build("log/output.log").unwrap();
-
-    let console_appender = ConsoleAppender::builder()
-        .build();
+    let mut cli = cli::Cli::parse();
+    // nobody answers a prompt of a scheduled run, unmapped keys show up in the failures and on /status instead
+    if matches!(cli.command, Some(cli::Command::Daemon { .. })) {
+        cli.non_interactive = true;
+    }
+    let default_command = cli::Command::Upload(cli::UploadArgs::default());
+    let command = cli.command.as_ref().unwrap_or(&default_command);
+    let progress = match command {
+        cli::Command::Upload(args)
+        | cli::Command::Retry { upload: args, .. }
+        | cli::Command::Watch { upload: args, .. }
+        | cli::Command::Daemon { upload: args, .. } => {
+            ProgressMode::detect(args.no_progress)
+        }
+        _ => ProgressMode::Off,
+    };
 
-    info!("Attempting to Load settings file");
+    let mut log_settings = cli.log.to_settings();
+    // info lines would tear the progress bar apart, they still go to the log file
+    log_settings.quiet_console = progress == ProgressMode::Bar;
+    if let Err(e) = logging::init(&log_settings) {
+        eprintln!("Could not set up logging: {}", e);
+        exit(1);
+    }
 
-    let logconfig = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .appender(Appender::builder().build("console", Box::new(console_appender)))
-        .build(Root::builder()
-            .appender("logfile")
-            .appender("console")
-            .build(LevelFilter::Info)).unwrap();
+    match command {
+        cli::Command::Upload(args) => upload(&cli, args, None, progress).await,
+        cli::Command::Retry { categories, upload: args } => upload(&cli, args, Some(categories), progress).await,
+        cli::Command::Watch { debounce, upload: args } => {
+            watch::watch(&cli, args, Duration::from_secs(*debounce), progress).await
+        }
+        cli::Command::Daemon { schedule, listen, upload: args } => {
+            if !listen.ip().is_loopback() {
+                warn!("The status endpoint on {} lists invoice numbers and addresses and is reachable from other machines", listen);
+            }
+            daemon::daemon(&cli, args, schedule, *listen, progress).await
+        }
+        cli::Command::EncryptKey { ref output } => {
+            if let Err(e) = settings::encrypt_api_key(output, cli.profile.as_deref()) {
+                error!("Could not encrypt api key: {}", e);
+                exit(1);
+            }
+        }
+        cli::Command::Config { action: cli::ConfigCommand::Validate } => validate_config(),
+        cli::Command::Changed { update, flag, filter } => check_changes(&cli, filter, *update, *flag).await,
+        cli::Command::Verify { report_json } => verify_vouchers(&cli, report_json.as_deref()).await,
+        cli::Command::Payments { report_csv, report_json } => {
+            sync_payments(&cli, report_csv, report_json.as_deref()).await
+        }
+        cli::Command::Revenue { group_by, report_csv, report_json, filter } => {
+            summarize_revenue(&cli, group_by, filter, report_csv.as_deref(), report_json.as_deref())
+        }
+        cli::Command::State { action } => manage_state(&cli, action),
+        cli::Command::Prefix { action } => manage_prefixes(&cli, action),
+        cli::Command::Customer { action } => manage_customers(&cli, action),
+        cli::Command::CheckTax { csv } => {
+            let config = load_profile(&cli);
+            let invoices = read_invoices(csv);
+            if tax::check_invoices(&config, &invoices) > 0 {
+                exit(1);
+            }
+        }
+        cli::Command::Unmapped { csv } => {
+            let config = load_profile(&cli);
+            let invoices = read_invoices(csv);
+            mapping::list_unmapped(&config, &invoices);
+        }
+    }
+}
 
-    log4rs::init_config(logconfig).unwrap();
+/// Parses the invoice csv, exits if the file cannot be read.
+fn read_invoices(path: &str) -> Vec<InvoiceCSV> {
+    match read_invoice_csv(path.to_string()) {
+        Ok(invoices) => invoices,
+        Err(e) => {
+            error!("{}", e);
+            exit(1);
+        }
+    }
+}
 
-    let mut config = match settings::load_settings() {
+/// Loads the settings of the profile selected on the command line, exits if that fails.
+fn load_profile(cli: &cli::Cli) -> Config {
+    let config = settings::load_settings()
+        .and_then(|settings| settings.select_profile(cli.profile.as_deref()));
+    match config {
         Ok(config) => config,
         Err(e) => {
             error!("Error loading settings file: {}", e);
             exit(1);
         }
+    }
+}
+
+/// Loads the settings of the profiles selected on the command line, exits if that fails.
+fn load_configs(cli: &cli::Cli) -> Vec<Config> {
+    match settings::load_settings().and_then(|settings| select_configs(&settings, cli)) {
+        Ok(configs) => configs,
+        Err(e) => {
+            error!("Error loading settings file: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn manage_prefixes(cli: &cli::Cli, action: &cli::PrefixCommand) {
+    let mut config = load_profile(cli);
+    let result = match action {
+        cli::PrefixCommand::List => {
+            mapping::list_prefixes(&config);
+            Ok(())
+        }
+        cli::PrefixCommand::Add { prefix, path } => mapping::add_prefix(&mut config, prefix, path),
+        cli::PrefixCommand::Remove { prefix } => mapping::remove_prefix(&mut config, prefix),
+        cli::PrefixCommand::Rename { old, new } => mapping::rename_prefix(&mut config, old, new),
+        cli::PrefixCommand::Import { file } => mapping::import_prefixes(&mut config, file),
+        cli::PrefixCommand::Export { file } => mapping::export_prefixes(&config, file),
     };
+    if let Err(e) = result {
+        error!("{}", e);
+        exit(1);
+    }
+}
 
-    if config.validate() {
-        info!("Settings file loaded successfully");
-    } else {
-        error!("Settings file failed validation, attempting to get new config from user");
+fn manage_customers(cli: &cli::Cli, action: &cli::CustomerCommand) {
+    let mut config = load_profile(cli);
+    let result = match action {
+        cli::CustomerCommand::List => {
+            mapping::list_customers(&config);
+            Ok(())
+        }
+        cli::CustomerCommand::Add { address, customer_id, payment_terms } => {
+            mapping::add_customer(&mut config, address, customer_id, *payment_terms)
+        }
+        cli::CustomerCommand::Remove { address } => mapping::remove_customer(&mut config, address),
+        cli::CustomerCommand::Rename { old, new } => mapping::rename_customer(&mut config, old, new),
+        cli::CustomerCommand::Import { file } => mapping::import_customers(&mut config, file),
+        cli::CustomerCommand::Export { file } => mapping::export_customers(&config, file),
+    };
+    if let Err(e) = result {
+        error!("{}", e);
+        exit(1);
+    }
+}
 
-        settings::update_settings();
+fn manage_state(cli: &cli::Cli, action: &cli::StateCommand) {
+    let config = load_profile(cli);
+    let result = StateStore::open(settings::STATE_PATH, config.display_name()).and_then(|mut state| match action {
+        cli::StateCommand::Import { file } => {
+            let file = file.clone().unwrap_or_else(|| config.ledger_path());
+            state.import_ledger(&file).map(|_| ())
+        }
+        cli::StateCommand::Export { file } => {
+            let file = file.clone().unwrap_or_else(|| config.ledger_path());
+            state.export_ledger(&file).map(|count| info!("Exported {} uploaded invoices to {}", count, file))
+        }
+        cli::StateCommand::Show { invoice_number } => show_invoice_state(&state, invoice_number),
+    });
+    if let Err(e) = result {
+        error!("{}", e);
+        exit(1);
     }
+}
 
-    info!("Parsing invoices.csv file");
-    let invoices = invoice::invoice::read_invoice_csv("invoices.csv".into());
-    info!("Found {} invoices", invoices.len());
+fn show_invoice_state(state: &StateStore, invoice_number: &str) -> cli_lexuploader::Result<()> {
+    let Some(record) = state.get(invoice_number)? else {
+        println!("Invoice {} is unknown", invoice_number);
+        return Ok(());
+    };
+    println!("Invoice {}: {}", record.invoice_number, record.state);
+    println!("  voucher: {}", record.voucher_id.as_deref().unwrap_or("-"));
+    println!("  file: {}", record.file_path.as_deref().unwrap_or("-"));
+    println!("  attempts: {}", record.attempts);
+    if let Some(message) = &record.error_message {
+        println!("  last error: {}", message);
+    }
+    if let Some(correction) = &record.correction {
+        println!("  needs correction: {}", correction);
+    }
+    for event in state.events(invoice_number)? {
+        println!("  {} {}{}", event.at, event.state, event.message.map(|message| format!(" ({})", message)).unwrap_or_default());
+    }
+    Ok(())
+}
 
-    info!("Parsing done_invoices.csv file");
+/// Reports the uploaded invoices that changed afterwards. With `update` the vouchers of changed rows are
+/// replaced, with `flag` the remaining ones are marked for manual correction. Exits with 1 if changes remain.
+async fn check_changes(cli: &cli::Cli, filter: &cli::FilterArgs, update: bool, flag: bool) {
+    let configs = load_configs(cli);
+    let invoices = read_invoices(INVOICE_CSV);
+    let filter = filter.to_filter();
 
-    let done_invoices = invoice::
-    invoice::try_read_done_invoices_csv("done_invoices.csv".into());
-    info!("Found {} done invoices", done_invoices.len());
+    let mut unresolved = 0;
+    for (mut config, batch) in settings::route(configs, &invoices, cli.all_profiles) {
+        let batch = filter.apply(batch, &config);
+        config.set_interactive(!cli.non_interactive);
+        let profile = config.display_name().to_string();
+        let result = StateStore::open(settings::STATE_PATH, &profile)
+            .and_then(|state| changes::detect_changes(&state, &config, &batch).map(|changed| (state, changed)));
+        let (state, changed) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error!("Could not check the invoices of profile {}: {}", profile, e);
+                exit(1);
+            }
+        };
+        if changed.is_empty() {
+            info!("No uploaded invoice of profile {} changed", profile);
+            continue;
+        }
+        println!("{} uploaded invoices of profile {} changed:", changed.len(), profile);
+        print!("{}", changes::render_changes(&changed));
 
-    let to_upload = invoices.iter().filter(|invoice| {
-        // checks if the invoice number is in done_invoices
-        !done_invoices.iter().any(|done_invoice| invoice.invoice_number() == done_invoice.invoice_number())
-    }).collect::<Vec<_>>();
+        let mut uploader = Uploader::new(config);
+        if update && changed.iter().any(ChangedInvoice::updatable) {
+            if let Err(e) = uploader.prepare().await {
+                error!("Api key check failed: {}", e);
+                exit(1);
+            }
+        }
+        for change in &changed {
+            let invoice_number = change.invoice.invoice_number();
+            if update && change.updatable() {
+                let voucher_id = change.record.voucher_id.as_deref().unwrap_or_default();
+                match uploader.update_voucher(change.invoice, voucher_id).await {
+                    Ok(()) => {
+                        let file_hash = change.invoice.mapped_file_path(uploader.config())
+                            .and_then(|path| cli_lexuploader::state::file_hash(&path).ok());
+                        if let Err(e) = state.record_updated(invoice_number, &change.invoice.content_hash(), file_hash.as_deref()) {
+                            error!("{}", e);
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Could not update voucher {} of invoice {}: {}", voucher_id, invoice_number, e);
+                        if flag {
+                            let reason = format!("{}, update failed: {}", change.correction(), e);
+                            if let Err(e) = state.record_flagged(invoice_number, &reason) {
+                                error!("{}", e);
+                            }
+                        }
+                        unresolved += 1;
+                        continue;
+                    }
+                }
+            }
+            if flag {
+                if let Err(e) = state.record_flagged(invoice_number, &change.correction()) {
+                    error!("{}", e);
+                }
+            }
+            unresolved += 1;
+        }
+    }
+    if unresolved > 0 {
+        warn!("{} changed invoices need a manual correction in lexoffice", unresolved);
+        exit(1);
+    }
+}
 
-    info!("Found {} invoices to upload", to_upload.len());
-    let mut invoices_uploaded = done_invoices;
+/// Checks every uploaded invoice of the selected profiles against lexoffice, exits with 1 if any is not ok.
+async fn verify_vouchers(cli: &cli::Cli, report_json: Option<&str>) {
+    let configs = load_configs(cli);
+    let invoices = read_invoices(INVOICE_CSV);
 
-    for invoice in to_upload {
-        debug!("Uploading invoice {}", invoice.invoice_number());
-        let time_start = std::time::Instant::now();
-        let result =  invoice.upload(&mut config).await;
+    let mut entries = vec![];
+    for mut config in configs {
+        config.set_interactive(!cli.non_interactive);
+        let mut uploader = Uploader::new(config);
+        if let Err(e) = uploader.prepare().await {
+            error!("Api key check failed: {}", e);
+            exit(1);
+        }
+        // an unmapped address is reported as a problem of the invoice, not asked for
+        let config = uploader.config_mut();
+        config.set_interactive(false);
+        let result = match StateStore::open_with_ledger(settings::STATE_PATH, config.display_name(), &config.ledger_path()) {
+            Ok(state) => verify::verify(config, &state, &invoices).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(profile_entries) => entries.extend(profile_entries),
+            Err(e) => {
+                error!("Could not verify profile {}: {}", config.display_name(), e);
+                exit(1);
+            }
+        }
+    }
 
+    print!("{}", verify::render_verify(&entries));
+    if let Some(path) = report_json {
+        match verify::write_json(&entries, path) {
+            Ok(()) => info!("Wrote report to {}", path),
+            Err(e) => error!("Could not write report to {}: {}", path, e),
+        }
+    }
+    if entries.iter().any(|entry| entry.status != VerifyStatus::Ok) {
+        exit(1);
+    }
+}
+
+/// Writes the payment status of every uploaded invoice of the selected profiles to the open items report.
+async fn sync_payments(cli: &cli::Cli, report_csv: &str, report_json: Option<&str>) {
+    let configs = load_configs(cli);
+    let invoices = read_invoices(INVOICE_CSV);
+
+    let mut items = vec![];
+    for mut config in configs {
+        config.set_interactive(!cli.non_interactive);
+        let mut uploader = Uploader::new(config);
+        if let Err(e) = uploader.prepare().await {
+            error!("Api key check failed: {}", e);
+            exit(1);
+        }
+        let config = uploader.config();
+        let result = match StateStore::open_with_ledger(settings::STATE_PATH, config.display_name(), &config.ledger_path()) {
+            Ok(state) => payments::open_items(config, &state, &invoices).await,
+            Err(e) => Err(e),
+        };
         match result {
-            Err(_) => {
-                error!("Error uploading invoice {}", invoice.invoice_number());
+            Ok(profile_items) => items.extend(profile_items),
+            Err(e) => {
+                error!("Could not fetch the payments of profile {}: {}", config.display_name(), e);
+                exit(1);
+            }
+        }
+    }
+
+    print!("{}", payments::render_open_items(&items));
+    match payments::write_csv(&items, report_csv) {
+        Ok(()) => info!("Wrote open items to {}", report_csv),
+        Err(e) => {
+            error!("Could not write open items to {}: {}", report_csv, e);
+            exit(1);
+        }
+    }
+    if let Some(path) = report_json {
+        match payments::write_json(&items, path) {
+            Ok(()) => info!("Wrote open items to {}", path),
+            Err(e) => error!("Could not write open items to {}: {}", path, e),
+        }
+    }
+}
+
+/// Prints net, tax and gross sums of the invoices in invoices.csv grouped by `dimensions`. The upload status
+/// comes from the state database of the profile each invoice is routed to, invoices no profile handles are `unrouted`.
+fn summarize_revenue(cli: &cli::Cli, dimensions: &[revenue::Dimension], filter: &cli::FilterArgs,
+                     report_csv: Option<&str>, report_json: Option<&str>) {
+    let (top_level, configs) = match settings::load_settings()
+        .and_then(|top_level| select_configs(&top_level, cli).map(|configs| (top_level, configs))) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("Error loading settings file: {}", e);
+            exit(1);
+        }
+    };
+    // other currencies than EUR are not uploaded but belong in the summary
+    let invoices = match read_all_invoice_csv(INVOICE_CSV.to_string()) {
+        Ok(invoices) => invoices,
+        Err(e) => {
+            error!("{}", e);
+            exit(1);
+        }
+    };
+    let filter = filter.to_filter();
+
+    let mut rows = vec![];
+    let (batches, unrouted) = settings::route_with_unrouted(configs, &invoices, cli.all_profiles);
+    for (config, batch) in batches {
+        let batch = filter.apply(batch, &config);
+        let result = StateStore::open_with_ledger(settings::STATE_PATH, config.display_name(), &config.ledger_path())
+            .and_then(|state| {
+                batch.into_iter()
+                    .map(|invoice| revenue::upload_status(&state, invoice.invoice_number()).map(|status| (invoice, status)))
+                    .collect::<Result<Vec<_>, Error>>()
+            });
+        match result {
+            Ok(profile_rows) => rows.extend(profile_rows),
+            Err(e) => {
+                error!("Could not read the upload state of profile {}: {}", config.display_name(), e);
+                exit(1);
+            }
+        }
+    }
+    // customers of invoices no profile handles are resolved with the top level settings
+    let unrouted = filter.apply(unrouted, &top_level);
+    if !unrouted.is_empty() {
+        warn!("No profile handles {} invoices, they are summed with the status {}", unrouted.len(), revenue::UNROUTED);
+    }
+    rows.extend(unrouted.into_iter().map(|invoice| (invoice, revenue::UNROUTED)));
+
+    let report = revenue::summarize(dimensions, &rows);
+    print!("{}", revenue::render_revenue(&report));
+    if let Some(path) = report_csv {
+        match revenue::write_csv(&report, path) {
+            Ok(()) => info!("Wrote revenue summary to {}", path),
+            Err(e) => error!("Could not write revenue summary to {}: {}", path, e),
+        }
+    }
+    if let Some(path) = report_json {
+        match revenue::write_json(&report, path) {
+            Ok(()) => info!("Wrote revenue summary to {}", path),
+            Err(e) => error!("Could not write revenue summary to {}: {}", path, e),
+        }
+    }
+}
+
+fn validate_config() {
+    // report problems as found on disk, the migration only runs on regular use
+    match config_check::validate_config_file(settings::CONFIG_PATH) {
+        Ok(issues) if issues.is_empty() => info!("{} is valid", settings::CONFIG_PATH),
+        Ok(issues) => {
+            for issue in &issues {
+                error!("{}", issue);
+            }
+            error!("Found {} problems in {}", issues.len(), settings::CONFIG_PATH);
+            exit(1);
+        }
+        Err(e) => {
+            error!("Could not read {}: {}", settings::CONFIG_PATH, e);
+            exit(1);
+        }
+    }
+}
+
+/// Uploads the pending invoices, or with `retry` only those in the failures file of each profile.
+async fn upload(cli: &cli::Cli, args: &cli::UploadArgs, retry: Option<&[ErrorCategory]>, progress: ProgressMode) {
+    let Some(report) = run_uploads(cli, args, retry, progress, false).await else {
+        exit(1);
+    };
+    print!("{}", report.render_text());
+    write_report(&report, args);
+    if !report.failed_profiles.is_empty() {
+        exit(1);
+    }
+}
+
+/// The profiles selected with --profile or --all-profiles.
+fn select_configs(settings: &Config, cli: &cli::Cli) -> cli_lexuploader::Result<Vec<Config>> {
+    settings.select_configs(cli.profile.as_deref(), cli.all_profiles)
+}
+
+/// Uploads the pending invoices of invoices.csv for every selected profile, or with `retry` only those
+/// in the failures file. With `hold_back` invoices whose pdf does not exist yet are left for a later run.
+/// Returns None if the settings or the csv could not be read.
+async fn run_uploads(cli: &cli::Cli, args: &cli::UploadArgs, retry: Option<&[ErrorCategory]>, progress: ProgressMode,
+                     hold_back: bool) -> Option<RunReport> {
+    let mut report = RunReport::new(chrono::Local::now());
+    let filter = args.to_filter();
+    let configs = match settings::load_settings().and_then(|settings| select_configs(&settings, cli)) {
+        Ok(configs) => configs,
+        Err(e) => {
+            error!("Error loading settings file: {}", e);
+            return None;
+        }
+    };
+
+    info!("Parsing {} file", INVOICE_CSV);
+    let invoices = match read_invoice_csv(INVOICE_CSV.to_string()) {
+        Ok(invoices) => invoices,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
+    info!("Found {} invoices", invoices.len());
+
+    let batches = settings::route(configs, &invoices, cli.all_profiles);
+    let mut remaining = filter.limit;
+    for (config, mut batch) in batches {
+        if remaining == Some(0) {
+            info!("Reached the limit of {} invoices", filter.limit.unwrap_or_default());
+            break;
+        }
+        if let Some(categories) = retry {
+            match failures::failed_invoice_numbers(&config.failures_path(), categories) {
+                Ok(numbers) => batch.retain(|invoice| numbers.contains(invoice.invoice_number())),
+                Err(e) => {
+                    error!("Could not read {}: {}", config.failures_path(), e);
+                    report.add_failed_profile(config.display_name());
+                    continue;
+                }
+            }
+            info!("Retrying {} failed invoices of profile {}", batch.len(), config.display_name());
+            if batch.is_empty() {
                 continue;
-            },
-            Ok(()) => {
-                debug!("Invoice {} uploaded successfully", invoice.invoice_number());
-                invoices_uploaded.push(CompletedInvoices::new(invoice));
             }
         }
+        let count = batch.len();
+        let mut batch = filter.apply(batch, &config);
+        if batch.len() != count {
+            info!("Selected {} of {} invoices for profile {}", batch.len(), count, config.display_name());
+        }
+        if hold_back {
+            hold_back_missing_files(&config, &mut batch);
+        }
+        if batch.is_empty() && (cli.all_profiles || count > 0) {
+            info!("No invoices for profile {}", config.display_name());
+            continue;
+        }
+        let profile = config.display_name().to_string();
+        match upload_invoices(config, batch, cli.non_interactive, remaining, progress).await {
+            Some(summary) => {
+                remaining = remaining.map(|limit| limit.saturating_sub(summary.uploaded.len() + summary.failed.len()));
+                report.add_summary(&profile, &summary);
+            }
+            None => report.add_failed_profile(&profile),
+        }
+    }
+    Some(report)
+}
 
-        info!("Uploaded invoice {} in {}ms", invoice.invoice_number(), time_start.elapsed().as_millis());
+/// Removes the invoices whose pdf is not there yet, invoices with an unmapped prefix are kept.
+fn hold_back_missing_files(config: &Config, batch: &mut Vec<&InvoiceCSV>) {
+    let count = batch.len();
+    batch.retain(|invoice| match invoice.mapped_file_path(config) {
+        Some(path) => Path::new(&path).exists(),
+        None => true,
+    });
+    if batch.len() != count {
+        info!("Holding back {} invoices of profile {} until their pdf exists", count - batch.len(), config.display_name());
     }
+}
 
-    info!("Writing done_invoices.csv file");
-    invoice::invoice::write_done_invoice_csv(&"done_invoices.csv".to_string(), &invoices_uploaded);
+/// Writes the report files requested on the command line, a failed write does not abort the run.
+fn write_report(report: &RunReport, args: &cli::UploadArgs) {
+    let outputs = [
+        (&args.report_csv, "csv"),
+        (&args.report_json, "json"),
+        (&args.report_html, "html"),
+    ];
+    for (path, format) in outputs {
+        let Some(path) = path else { continue };
+        let result = match format {
+            "csv" => report.write_csv(path),
+            "json" => report.write_json(path),
+            _ => report.write_html(path),
+        };
+        match result {
+            Ok(()) => info!("Wrote report to {}", path),
+            Err(e) => error!("Could not write report to {}: {}", path, e),
+        }
+    }
+}
 
+/// Uploads the invoices of one profile and records them in the state database.
+/// At most `limit` invoices that are not uploaded yet are uploaded.
+/// Returns None if the profile could not be used at all.
+async fn upload_invoices(mut config: Config, mut invoices: Vec<&InvoiceCSV>, non_interactive: bool,
+                         limit: Option<usize>, progress: ProgressMode) -> Option<UploadSummary> {
+    info!("Using profile {}", config.display_name());
+    config.set_interactive(!non_interactive);
+    let mut display = Progress::new(progress, config.display_name());
+    let mut uploader = Uploader::new(config).on_progress(move |event| display.handle(event));
+    if let Err(e) = uploader.prepare().await {
+        error!("Api key check failed: {}", e);
+        return None;
+    }
+
+    let mut skipped = 0;
+    if limit.is_some() {
+        let mut pending = match uploader.pending(&invoices) {
+            Ok(pending) => pending,
+            Err(e) => {
+                error!("{}", e);
+                return None;
+            }
+        };
+        skipped = invoices.len() - pending.len();
+        filter::limit_invoices(&mut pending, limit);
+        invoices = pending;
+    }
+
+    match uploader.run(&invoices).await {
+        Ok(mut summary) => {
+            summary.skipped += skipped;
+            info!("Uploaded {} invoices, {} failed", summary.uploaded.len(), summary.failed.len());
+            Some(summary)
+        }
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
 }
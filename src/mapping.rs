@@ -0,0 +1,204 @@
+use std::collections::BTreeSet;
+use log::{info, warn};
+use crate::invoice::invoice::InvoiceCSV;
+use crate::error::{Error, Result};
+use crate::settings::{is_valid_customer_id, Config, Customer, PaymentTerms, PrefixConfig};
+
+pub fn list_prefixes(config: &Config) {
+    println!("Prefixes of the {} profile:", config.display_name());
+    for prefix in config.prefixes.iter().flatten() {
+        println!("  {} -> {}", prefix.prefix, prefix.path);
+    }
+}
+
+/// Adds the prefix or points an existing one to the new folder.
+pub fn add_prefix(config: &mut Config, prefix: &str, path: &str) -> Result<()> {
+    let prefixes = config.prefixes.get_or_insert_with(Vec::new);
+    match prefixes.iter_mut().find(|entry| entry.prefix == prefix) {
+        Some(entry) => {
+            info!("Changing folder of prefix {} from {} to {}", prefix, entry.path, path);
+            entry.path = path.to_string();
+        }
+        None => {
+            info!("Adding prefix {} -> {}", prefix, path);
+            prefixes.push(PrefixConfig { prefix: prefix.to_string(), path: path.to_string() });
+        }
+    }
+    config.store()
+}
+
+pub fn remove_prefix(config: &mut Config, prefix: &str) -> Result<()> {
+    let prefixes = config.prefixes.get_or_insert_with(Vec::new);
+    let count = prefixes.len();
+    prefixes.retain(|entry| entry.prefix != prefix);
+    if prefixes.len() == count {
+        return Err(Error::config(format!("Prefix {} is not configured", prefix)));
+    }
+    info!("Removed prefix {}", prefix);
+    config.store()
+}
+
+pub fn rename_prefix(config: &mut Config, old: &str, new: &str) -> Result<()> {
+    if config.has_prefix(new) {
+        return Err(Error::config(format!("Prefix {} is already configured", new)));
+    }
+    let entry = config.prefixes.iter_mut().flatten()
+        .find(|entry| entry.prefix == old)
+        .ok_or_else(|| Error::config(format!("Prefix {} is not configured", old)))?;
+    entry.prefix = new.to_string();
+    info!("Renamed prefix {} to {}", old, new);
+    config.store()
+}
+
+/// Imports a `prefix,path` csv, existing prefixes are overwritten.
+pub fn import_prefixes(config: &mut Config, path: &str) -> Result<()> {
+    let mut rdr = csv::Reader::from_path(path).map_err(|e| Error::csv(path, e))?;
+    let mut imported = 0;
+    for result in rdr.deserialize() {
+        let entry: PrefixConfig = result.map_err(|e| Error::csv(path, e))?;
+        let prefixes = config.prefixes.get_or_insert_with(Vec::new);
+        match prefixes.iter_mut().find(|existing| existing.prefix == entry.prefix) {
+            Some(existing) => existing.path = entry.path,
+            None => prefixes.push(entry),
+        }
+        imported += 1;
+    }
+    info!("Imported {} prefixes from {}", imported, path);
+    config.store()
+}
+
+pub fn export_prefixes(config: &Config, path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_path(path).map_err(|e| Error::csv(path, e))?;
+    for prefix in config.prefixes.iter().flatten() {
+        wtr.serialize(prefix).map_err(|e| Error::csv(path, e))?;
+    }
+    wtr.flush()?;
+    info!("Exported prefixes to {}", path);
+    Ok(())
+}
+
+pub fn list_customers(config: &Config) {
+    println!("Customers of the {} profile:", config.display_name());
+    for customer in config.customers.iter().flatten() {
+        match customer.payment_terms {
+            Some(terms) => println!("  {} -> {} ({})", customer.customer_adress, customer.customer_id, terms),
+            None => println!("  {} -> {}", customer.customer_adress, customer.customer_id),
+        }
+    }
+}
+
+/// Adds the address or points an existing one to the new contact. Existing payment terms are kept
+/// unless new ones are given.
+pub fn add_customer(config: &mut Config, address: &str, customer_id: &str, payment_terms: Option<PaymentTerms>) -> Result<()> {
+    if !is_valid_customer_id(customer_id) {
+        return Err(Error::config(format!("{} is not a valid uuid", customer_id)));
+    }
+    let customers = config.customers.get_or_insert_with(Vec::new);
+    match customers.iter_mut().find(|entry| entry.customer_adress == address) {
+        Some(entry) => {
+            info!("Changing customer id of {} from {} to {}", address, entry.customer_id, customer_id);
+            entry.customer_id = customer_id.to_string();
+            entry.payment_terms = payment_terms.or(entry.payment_terms);
+        }
+        None => {
+            info!("Adding customer {} -> {}", address, customer_id);
+            customers.push(Customer {
+                customer_id: customer_id.to_string(),
+                customer_adress: address.to_string(),
+                payment_terms,
+            });
+        }
+    }
+    config.store()
+}
+
+pub fn remove_customer(config: &mut Config, address: &str) -> Result<()> {
+    let customers = config.customers.get_or_insert_with(Vec::new);
+    let count = customers.len();
+    customers.retain(|entry| entry.customer_adress != address);
+    if customers.len() == count {
+        return Err(Error::config(format!("Address {} is not configured", address)));
+    }
+    info!("Removed customer {}", address);
+    config.store()
+}
+
+/// Changes the address of a mapping, e.g. after the marketplace changed its billing address.
+pub fn rename_customer(config: &mut Config, old: &str, new: &str) -> Result<()> {
+    if config.customers.iter().flatten().any(|entry| entry.customer_adress == new) {
+        return Err(Error::config(format!("Address {} is already configured", new)));
+    }
+    let entry = config.customers.iter_mut().flatten()
+        .find(|entry| entry.customer_adress == old)
+        .ok_or_else(|| Error::config(format!("Address {} is not configured", old)))?;
+    entry.customer_adress = new.to_string();
+    info!("Renamed address {} to {}", old, new);
+    config.store()
+}
+
+/// Imports a `customer_id,customer_adress[,payment_terms]` csv, rows with invalid uuids are skipped.
+pub fn import_customers(config: &mut Config, path: &str) -> Result<()> {
+    let mut rdr = csv::Reader::from_path(path).map_err(|e| Error::csv(path, e))?;
+    let mut imported = 0;
+    for result in rdr.deserialize() {
+        let entry: Customer = result.map_err(|e| Error::csv(path, e))?;
+        if !is_valid_customer_id(&entry.customer_id) {
+            warn!("Skipping {}, {} is not a valid uuid", entry.customer_adress, entry.customer_id);
+            continue;
+        }
+        let customers = config.customers.get_or_insert_with(Vec::new);
+        match customers.iter_mut().find(|existing| existing.customer_adress == entry.customer_adress) {
+            Some(existing) => {
+                existing.customer_id = entry.customer_id;
+                existing.payment_terms = entry.payment_terms.or(existing.payment_terms);
+            }
+            None => customers.push(entry),
+        }
+        imported += 1;
+    }
+    info!("Imported {} customers from {}", imported, path);
+    config.store()
+}
+
+pub fn export_customers(config: &Config, path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_path(path).map_err(|e| Error::csv(path, e))?;
+    // written by hand, serialize would drop the payment terms column of customers without terms
+    wtr.write_record(["customer_id", "customer_adress", "payment_terms"]).map_err(|e| Error::csv(path, e))?;
+    for customer in config.customers.iter().flatten() {
+        let payment_terms = customer.payment_terms.map(|terms| terms.to_string()).unwrap_or_default();
+        wtr.write_record([customer.customer_id.as_str(), customer.customer_adress.as_str(), payment_terms.as_str()])
+            .map_err(|e| Error::csv(path, e))?;
+    }
+    wtr.flush()?;
+    info!("Exported customers to {}", path);
+    Ok(())
+}
+
+/// The prefixes and addresses of the invoices that would trigger a prompt during upload.
+pub fn unmapped<'a>(config: &Config, invoices: impl IntoIterator<Item = &'a InvoiceCSV>) -> (BTreeSet<String>, BTreeSet<String>) {
+    let mut prefixes = BTreeSet::new();
+    let mut addresses = BTreeSet::new();
+    for invoice in invoices {
+        if !config.has_prefix(&invoice.prefix()) {
+            prefixes.insert(invoice.prefix());
+        }
+        if config.customer_id(invoice.billing_address()).is_none() {
+            addresses.insert(invoice.billing_address().to_string());
+        }
+    }
+    (prefixes, addresses)
+}
+
+/// Prints the prefixes and addresses of the invoices that would trigger a prompt during upload.
+pub fn list_unmapped(config: &Config, invoices: &[InvoiceCSV]) {
+    let (prefixes, addresses) = unmapped(config, invoices);
+
+    println!("{} unmapped prefixes in the {} profile:", prefixes.len(), config.display_name());
+    for prefix in prefixes {
+        println!("  {}", prefix);
+    }
+    println!("{} unmapped addresses in the {} profile:", addresses.len(), config.display_name());
+    for address in addresses {
+        println!("  {}", address);
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::Path;
+use log::info;
+use serde_yaml::{Mapping, Value};
+use crate::error::{Error, Result};
+
+/// Schema version written by this build. Raise it together with a new entry in `MIGRATIONS`.
+pub const CURRENT_VERSION: u64 = 1;
+
+/// `MIGRATIONS[n]` upgrades a config of version n to version n + 1.
+const MIGRATIONS: &[fn(&mut Mapping) -> Result<()>] = &[
+    migrate_v0_to_v1,
+];
+
+/// Version of a raw config, files written before versioning count as version 0.
+pub fn config_version(config: &Mapping) -> Result<u64> {
+    match config.get(&Value::from("version")) {
+        None => Ok(0),
+        Some(version) => version.as_u64().ok_or_else(|| Error::config("version must be a positive number")),
+    }
+}
+
+/// Upgrades the config file at `path` to `CURRENT_VERSION`.
+/// The original file is kept as `<path>.v<version>.bak` before anything is written.
+pub fn migrate_config_file(path: &str) -> Result<()> {
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(path)?;
+    let mut config = match serde_yaml::from_str::<Value>(&content)? {
+        Value::Mapping(config) => config,
+        // an empty file is filled with defaults by confy
+        Value::Null => return Ok(()),
+        _ => return Err(Error::config(format!("{} does not contain a yaml mapping", path))),
+    };
+
+    let version = config_version(&config)?;
+    if version == CURRENT_VERSION {
+        return Ok(());
+    }
+    if version > CURRENT_VERSION {
+        return Err(Error::config(format!("{} has version {} but this program only supports up to version {}, please update",
+                           path, version, CURRENT_VERSION)));
+    }
+
+    let backup = backup_path(path, version);
+    fs::copy(path, &backup)?;
+    info!("Backed up {} to {} before migrating it", path, backup);
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut config)?;
+        info!("Migrated {} from version {} to {}", path, from, from + 1);
+    }
+    config.insert(Value::from("version"), Value::from(CURRENT_VERSION));
+
+    fs::write(path, serde_yaml::to_string(&config)?)?;
+    Ok(())
+}
+
+fn backup_path(path: &str, version: u64) -> String {
+    let backup = format!("{}.v{}.bak", path, version);
+    if !Path::new(&backup).exists() {
+        return backup;
+    }
+    // never overwrite an earlier backup
+    format!("{}.v{}.{}.bak", path, version, chrono::Local::now().format("%Y%m%d%H%M%S"))
+}
+
+/// Version 0 allowed `prefixes` and `customers` to be missing or null.
+fn migrate_v0_to_v1(config: &mut Mapping) -> Result<()> {
+    for key in ["prefixes", "customers"] {
+        let key = Value::from(key);
+        if matches!(config.get(&key), None | Some(Value::Null)) {
+            config.insert(key, Value::Sequence(vec![]));
+        }
+    }
+    Ok(())
+}
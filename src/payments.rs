@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::fs;
+use chrono::{Local, NaiveDate};
+use log::{info, warn};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use crate::error::{Error, Result};
+use crate::invoice::invoice::InvoiceCSV;
+use crate::lexoffice;
+use crate::report::render_table;
+use crate::settings::Config;
+use crate::state::{InvoiceState, StateStore};
+use crate::verify::{self, VoucherLookup};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaymentStatus {
+    /// past the due date and not fully paid
+    Overdue,
+    Open,
+    PartiallyPaid,
+    Paid,
+    Voided,
+    /// the payment information could not be fetched
+    Unknown,
+}
+
+impl PaymentStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            PaymentStatus::Overdue => "overdue",
+            PaymentStatus::Open => "open",
+            PaymentStatus::PartiallyPaid => "partially-paid",
+            PaymentStatus::Paid => "paid",
+            PaymentStatus::Voided => "voided",
+            PaymentStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// One line of the open items report.
+#[derive(Serialize, Debug, Clone)]
+pub struct OpenItem {
+    pub profile: String,
+    pub invoice_number: String,
+    pub internal_reference: Option<String>,
+    /// `None` if the voucher of an imported invoice could not be found by its number
+    pub voucher_id: Option<String>,
+    pub status: PaymentStatus,
+    pub currency: Option<String>,
+    pub total: Option<Decimal>,
+    pub open_amount: Option<Decimal>,
+    pub due_date: Option<NaiveDate>,
+    pub paid_date: Option<NaiveDate>,
+    pub error: Option<String>,
+}
+
+/// Fetches voucher and payment information of every uploaded invoice. Invoices without a recorded voucher id
+/// are looked up by number, see `verify::lookup_voucher_id`, and listed as unknown if that fails.
+/// The internal reference is taken from the csv row if the invoice is still in it.
+pub async fn open_items(config: &Config, state: &StateStore, invoices: &[InvoiceCSV]) -> Result<Vec<OpenItem>> {
+    let rows: HashMap<&str, &InvoiceCSV> = invoices.iter().map(|invoice| (invoice.invoice_number(), invoice)).collect();
+    let records: Vec<_> = state.records()?.into_iter()
+        .filter(|record| record.state == InvoiceState::FileUploaded)
+        .collect();
+    info!("Fetching the payments of {} vouchers of profile {}", records.len(), config.display_name());
+
+    let client = Client::new();
+    let today = Local::now().date_naive();
+    let mut items = vec![];
+    for record in records {
+        let mut item = OpenItem {
+            profile: config.display_name().to_string(),
+            internal_reference: rows.get(record.invoice_number.as_str())
+                .and_then(|invoice| invoice.internal_reference())
+                .map(str::to_string),
+            invoice_number: record.invoice_number.clone(),
+            voucher_id: None,
+            status: PaymentStatus::Unknown,
+            currency: None,
+            total: None,
+            open_amount: None,
+            due_date: None,
+            paid_date: None,
+            error: None,
+        };
+
+        let voucher_id = match verify::lookup_voucher_id(&client, config, state, &record).await {
+            Ok(VoucherLookup::Found(voucher_id)) => voucher_id,
+            Ok(VoucherLookup::NotFound) => {
+                item.error = Some("no voucher with this number exists in lexoffice".to_string());
+                items.push(item);
+                continue;
+            }
+            Ok(VoucherLookup::Ambiguous(count)) => {
+                item.error = Some(format!("{} vouchers in lexoffice have this number", count));
+                items.push(item);
+                continue;
+            }
+            Err(e) => {
+                warn!("Could not look up the voucher of invoice {}: {}", item.invoice_number, e);
+                item.error = Some(e.to_string());
+                items.push(item);
+                continue;
+            }
+        };
+        item.voucher_id = Some(voucher_id.clone());
+
+        let fetched = match lexoffice::get_voucher(&client, config, &voucher_id).await {
+            Ok(voucher) => lexoffice::get_payment(&client, config, &voucher_id).await.map(|payment| (voucher, payment)),
+            Err(e) => Err(e),
+        };
+        match fetched {
+            Ok((voucher, payment)) => {
+                item.total = voucher.total_gross_amount;
+                item.due_date = parse_date(voucher.due_date.as_deref());
+                item.currency = payment.currency;
+                item.open_amount = payment.open_amount;
+                item.paid_date = parse_date(payment.paid_date.as_deref());
+                item.status = status(payment.voucher_status.as_deref(), item.open_amount, item.total, item.due_date, today);
+            }
+            Err(e) => {
+                warn!("Could not fetch the payments of invoice {}: {}", item.invoice_number, e);
+                item.error = Some(e.to_string());
+            }
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+fn status(voucher_status: Option<&str>, open_amount: Option<Decimal>, total: Option<Decimal>, due_date: Option<NaiveDate>,
+          today: NaiveDate) -> PaymentStatus {
+    match voucher_status {
+        Some("voided") => return PaymentStatus::Voided,
+        Some("paid") | Some("paidoff") => return PaymentStatus::Paid,
+        _ => {}
+    }
+    let Some(open_amount) = open_amount else { return PaymentStatus::Unknown };
+    if open_amount <= Decimal::ZERO {
+        return PaymentStatus::Paid;
+    }
+    if due_date.is_some_and(|due_date| due_date < today) {
+        PaymentStatus::Overdue
+    } else if total.is_some_and(|total| open_amount < total) {
+        PaymentStatus::PartiallyPaid
+    } else {
+        PaymentStatus::Open
+    }
+}
+
+/// lexoffice dates are timestamps like `2023-01-15T00:00:00.000+01:00`, the date part is enough here.
+fn parse_date(timestamp: Option<&str>) -> Option<NaiveDate> {
+    timestamp.and_then(|timestamp| NaiveDate::parse_from_str(timestamp.get(..10)?, "%Y-%m-%d").ok())
+}
+
+/// The items that are not paid or voided, most urgent first, followed by a summary line.
+pub fn render_open_items(items: &[OpenItem]) -> String {
+    let mut open: Vec<&OpenItem> = items.iter()
+        .filter(|item| !matches!(item.status, PaymentStatus::Paid | PaymentStatus::Voided))
+        .collect();
+    open.sort_by_key(|item| (item.status, item.due_date));
+    let rows: Vec<Vec<String>> = open.iter().map(|item| vec![
+        item.invoice_number.clone(),
+        item.internal_reference.clone().unwrap_or_default(),
+        item.status.name().to_string(),
+        item.due_date.map(|date| date.to_string()).unwrap_or_default(),
+        format_amount(item.open_amount, &item.currency),
+        format_amount(item.total, &item.currency),
+        item.error.clone().unwrap_or_default(),
+    ]).collect();
+
+    let count = |status| items.iter().filter(|item| item.status == status).count();
+    let mut out = String::new();
+    if !rows.is_empty() {
+        out.push_str(&render_table(&["Invoice", "Reference", "Status", "Due", "Open", "Total", "Error"], &rows));
+    }
+    out.push_str(&format!("{} invoices: {} paid, {} partially paid, {} open, {} overdue, {} voided, {} unknown\n",
+                          items.len(), count(PaymentStatus::Paid), count(PaymentStatus::PartiallyPaid),
+                          count(PaymentStatus::Open), count(PaymentStatus::Overdue), count(PaymentStatus::Voided),
+                          count(PaymentStatus::Unknown)));
+    out
+}
+
+fn format_amount(amount: Option<Decimal>, currency: &Option<String>) -> String {
+    match amount {
+        Some(amount) => format!("{} {}", amount.round_dp(2), currency.as_deref().unwrap_or_default()).trim_end().to_string(),
+        None => String::new(),
+    }
+}
+
+pub fn write_csv(items: &[OpenItem], path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_path(path).map_err(|e| Error::csv(path, e))?;
+    wtr.write_record(["profile", "invoice_number", "internal_reference", "voucher_id", "status", "currency", "total",
+                      "open_amount", "due_date", "paid_date", "error"])
+        .map_err(|e| Error::csv(path, e))?;
+    for item in items {
+        wtr.write_record([
+            item.profile.clone(),
+            item.invoice_number.clone(),
+            item.internal_reference.clone().unwrap_or_default(),
+            item.voucher_id.clone().unwrap_or_default(),
+            item.status.name().to_string(),
+            item.currency.clone().unwrap_or_default(),
+            item.total.map(|total| total.to_string()).unwrap_or_default(),
+            item.open_amount.map(|amount| amount.to_string()).unwrap_or_default(),
+            item.due_date.map(|date| date.to_string()).unwrap_or_default(),
+            item.paid_date.map(|date| date.to_string()).unwrap_or_default(),
+            item.error.clone().unwrap_or_default(),
+        ]).map_err(|e| Error::csv(path, e))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn write_json(items: &[OpenItem], path: &str) -> Result<()> {
+    let json = serde_json::to_string_pretty(items).map_err(|e| Error::json(path, e))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(value: &str) -> Option<Decimal> {
+        Some(value.parse().expect("valid amount"))
+    }
+
+    fn date(day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(2024, 2, day)
+    }
+
+    #[test]
+    fn status_follows_the_open_amount() {
+        let today = date(15).expect("valid date");
+        let total = amount("119.00");
+        assert_eq!(status(Some("open"), amount("119.00"), total, date(29), today), PaymentStatus::Open);
+        assert_eq!(status(Some("open"), amount("50.00"), total, date(29), today), PaymentStatus::PartiallyPaid);
+        assert_eq!(status(Some("open"), amount("0"), total, date(1), today), PaymentStatus::Paid);
+        assert_eq!(status(Some("paidoff"), amount("119.00"), total, date(1), today), PaymentStatus::Paid);
+        assert_eq!(status(Some("voided"), amount("119.00"), total, date(1), today), PaymentStatus::Voided);
+        assert_eq!(status(Some("open"), None, total, date(29), today), PaymentStatus::Unknown);
+    }
+
+    #[test]
+    fn overdue_only_after_the_due_date() {
+        let today = date(15).expect("valid date");
+        let total = amount("119.00");
+        assert_eq!(status(Some("open"), amount("119.00"), total, date(14), today), PaymentStatus::Overdue);
+        assert_eq!(status(Some("open"), amount("50.00"), total, date(14), today), PaymentStatus::Overdue);
+        assert_eq!(status(Some("open"), amount("119.00"), total, date(15), today), PaymentStatus::Open);
+        assert_eq!(status(Some("open"), amount("119.00"), total, None, today), PaymentStatus::Open);
+    }
+
+    #[test]
+    fn dates_are_read_from_timestamps() {
+        assert_eq!(parse_date(Some("2024-02-14T00:00:00.000+01:00")), date(14));
+        assert_eq!(parse_date(Some("2024-02")), None);
+        assert_eq!(parse_date(None), None);
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+use indicatif::{ProgressBar, ProgressStyle};
+use cli_lexuploader::UploadEvent;
+
+/// Interval of the plain progress lines when stdout is not a terminal.
+const LINE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// progress bar on stderr, only if the output goes to a terminal
+    Bar,
+    /// a line on stdout every `LINE_INTERVAL`
+    Lines,
+    Off,
+}
+
+impl ProgressMode {
+    pub fn detect(disabled: bool) -> Self {
+        if disabled {
+            ProgressMode::Off
+        } else if std::io::stdout().is_terminal() && std::io::stderr().is_terminal() {
+            ProgressMode::Bar
+        } else {
+            ProgressMode::Lines
+        }
+    }
+}
+
+/// Shows the progress of one `Uploader::run`, fed through `Uploader::on_progress`.
+pub struct Progress {
+    mode: ProgressMode,
+    profile: String,
+    bar: Option<ProgressBar>,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    /// number of the last finished invoice
+    last_invoice: String,
+    started: Instant,
+    last_line: Instant,
+}
+
+impl Progress {
+    pub fn new(mode: ProgressMode, profile: &str) -> Self {
+        Self {
+            mode,
+            profile: profile.to_string(),
+            bar: None,
+            total: 0,
+            succeeded: 0,
+            failed: 0,
+            last_invoice: String::new(),
+            started: Instant::now(),
+            last_line: Instant::now(),
+        }
+    }
+
+    pub fn handle(&mut self, event: &UploadEvent<'_>) {
+        match event {
+            UploadEvent::Started { total } => self.start(*total),
+            UploadEvent::InvoiceStarted { invoice, .. } => {
+                if let Some(bar) = &self.bar {
+                    bar.set_message(self.message(invoice.invoice_number()));
+                }
+            }
+            UploadEvent::InvoiceFinished { invoice, error, .. } => {
+                match error {
+                    Some(_) => self.failed += 1,
+                    None => self.succeeded += 1,
+                }
+                self.last_invoice = invoice.invoice_number().to_string();
+                if let Some(bar) = &self.bar {
+                    bar.set_message(self.message(invoice.invoice_number()));
+                    bar.inc(1);
+                } else if self.mode == ProgressMode::Lines && self.last_line.elapsed() >= LINE_INTERVAL {
+                    self.last_line = Instant::now();
+                    println!("{}", self.line(invoice.invoice_number()));
+                }
+            }
+            UploadEvent::Finished { .. } => {
+                if let Some(bar) = self.bar.take() {
+                    bar.finish_and_clear();
+                } else if self.mode == ProgressMode::Lines && self.total > 0 {
+                    println!("{}", self.line(&self.last_invoice));
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, total: usize) {
+        self.total = total;
+        self.started = Instant::now();
+        self.last_line = Instant::now();
+        if self.mode != ProgressMode::Bar || total == 0 {
+            return;
+        }
+        let style = ProgressStyle::with_template("{prefix} [{elapsed_precise}] {wide_bar} {pos}/{len} {per_sec} ETA {eta} {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar());
+        // no steady tick, the bar is only redrawn between invoices so it does not cover mapping prompts
+        self.bar = Some(ProgressBar::new(total as u64).with_style(style).with_prefix(self.profile.clone()));
+    }
+
+    fn message(&self, invoice_number: &str) -> String {
+        format!("ok {} failed {} | {}", self.succeeded, self.failed, invoice_number)
+    }
+
+    fn processed(&self) -> usize {
+        self.succeeded + self.failed
+    }
+
+    /// e.g. `default: 40/200 (38 ok, 2 failed), 2.1 invoices/s, ETA 1m16s | RE-1040`
+    fn line(&self, invoice_number: &str) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { self.processed() as f64 / elapsed } else { 0.0 };
+        let remaining = self.total.saturating_sub(self.processed());
+        let eta = if rate > 0.0 { format_duration((remaining as f64 / rate) as u64) } else { "unknown".to_string() };
+        format!("{}: {}/{} ({} ok, {} failed), {:.1} invoices/s, ETA {} | {}",
+                self.profile, self.processed(), self.total, self.succeeded, self.failed, rate, eta, invoice_number)
+    }
+}
+
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
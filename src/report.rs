@@ -0,0 +1,209 @@
+use std::fmt::Write as _;
+use std::fs;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use crate::error::{Error, ErrorCategory, Result};
+use crate::uploader::UploadSummary;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryStatus {
+    Uploaded,
+    Failed,
+}
+
+/// One invoice of a run.
+#[derive(Serialize, Debug, Clone)]
+pub struct ReportEntry {
+    pub profile: String,
+    pub invoice_number: String,
+    pub status: EntryStatus,
+    pub voucher_id: Option<String>,
+    pub file: Option<String>,
+    pub duration_ms: u128,
+    pub category: Option<ErrorCategory>,
+    pub error: Option<String>,
+}
+
+/// Summary of a whole run over all profiles, rendered for the console or written to files.
+#[derive(Serialize, Debug, Clone)]
+pub struct RunReport {
+    pub started_at: DateTime<Local>,
+    pub finished_at: DateTime<Local>,
+    /// invoices that were already uploaded
+    pub skipped: usize,
+    pub entries: Vec<ReportEntry>,
+    /// profiles whose api key or state database could not be used
+    pub failed_profiles: Vec<String>,
+}
+
+impl RunReport {
+    pub fn new(started_at: DateTime<Local>) -> Self {
+        Self { started_at, finished_at: started_at, skipped: 0, entries: vec![], failed_profiles: vec![] }
+    }
+
+    /// Adds the outcome of one profile.
+    pub fn add_summary(&mut self, profile: &str, summary: &UploadSummary) {
+        self.skipped += summary.skipped;
+        for uploaded in &summary.uploaded {
+            self.entries.push(ReportEntry {
+                profile: profile.to_string(),
+                invoice_number: uploaded.invoice_number.clone(),
+                status: EntryStatus::Uploaded,
+                voucher_id: Some(uploaded.voucher_id.clone()),
+                file: Some(uploaded.file_path.clone()),
+                duration_ms: uploaded.duration.as_millis(),
+                category: None,
+                error: None,
+            });
+        }
+        for failed in &summary.failed {
+            self.entries.push(ReportEntry {
+                profile: profile.to_string(),
+                invoice_number: failed.invoice_number.clone(),
+                status: EntryStatus::Failed,
+                voucher_id: None,
+                file: failed.file_path.clone(),
+                duration_ms: failed.duration.as_millis(),
+                category: Some(failed.category()),
+                error: Some(failed.error.to_string()),
+            });
+        }
+        self.finished_at = Local::now();
+    }
+
+    pub fn add_failed_profile(&mut self, profile: &str) {
+        self.failed_profiles.push(profile.to_string());
+        self.finished_at = Local::now();
+    }
+
+    pub fn uploaded(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.status == EntryStatus::Uploaded).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.status == EntryStatus::Failed).count()
+    }
+
+    /// Plain text table for the console.
+    pub fn render_text(&self) -> String {
+        let headers = ["Invoice", "Status", "Voucher", "File", "Duration", "Error"];
+        let rows: Vec<Vec<String>> = self.entries.iter().map(|entry| vec![
+            entry.invoice_number.clone(),
+            status_name(entry.status).to_string(),
+            entry.voucher_id.clone().unwrap_or_default(),
+            entry.file.clone().unwrap_or_default(),
+            format!("{}ms", entry.duration_ms),
+            entry.error.clone().unwrap_or_default(),
+        ]).collect();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "Run from {} to {}: {} uploaded, {} failed, {} already done",
+                         self.started_at.format("%Y-%m-%d %H:%M:%S"), self.finished_at.format("%H:%M:%S"),
+                         self.uploaded(), self.failed(), self.skipped);
+        if !self.failed_profiles.is_empty() {
+            let _ = writeln!(out, "Profiles that could not be used: {}", self.failed_profiles.join(", "));
+        }
+        if !rows.is_empty() {
+            out.push_str(&render_table(&headers, &rows));
+        }
+        out
+    }
+
+    pub fn write_csv(&self, path: &str) -> Result<()> {
+        let mut wtr = csv::Writer::from_path(path).map_err(|e| Error::csv(path, e))?;
+        wtr.write_record(["profile", "invoice_number", "status", "voucher_id", "file", "duration_ms", "category", "error"])
+            .map_err(|e| Error::csv(path, e))?;
+        for entry in &self.entries {
+            wtr.write_record([
+                entry.profile.clone(),
+                entry.invoice_number.clone(),
+                status_name(entry.status).to_string(),
+                entry.voucher_id.clone().unwrap_or_default(),
+                entry.file.clone().unwrap_or_default(),
+                entry.duration_ms.to_string(),
+                entry.category.map(|category| category.to_string()).unwrap_or_default(),
+                entry.error.clone().unwrap_or_default(),
+            ]).map_err(|e| Error::csv(path, e))?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| Error::json(path, e))?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Single html file without external resources, so it can be mailed to the bookkeeper.
+    pub fn write_html(&self, path: &str) -> Result<()> {
+        fs::write(path, self.render_html())?;
+        Ok(())
+    }
+
+    fn render_html(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(out, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>lexoffice upload {}</title>\n\
+            <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+            td,th{{border:1px solid #ccc;padding:4px 8px;text-align:left}}\
+            tr.failed{{background:#fde2e2}}tr.uploaded{{background:#e6f4e6}}</style></head><body>\n",
+                       self.started_at.format("%Y-%m-%d %H:%M"));
+        let _ = writeln!(out, "<h1>lexoffice upload {}</h1>", self.started_at.format("%Y-%m-%d %H:%M:%S"));
+        let _ = writeln!(out, "<p>{} uploaded, {} failed, {} already done, finished at {}</p>",
+                         self.uploaded(), self.failed(), self.skipped, self.finished_at.format("%H:%M:%S"));
+        if !self.failed_profiles.is_empty() {
+            let _ = writeln!(out, "<p>Profiles that could not be used: {}</p>", escape_html(&self.failed_profiles.join(", ")));
+        }
+        out.push_str("<table>\n<tr><th>Profile</th><th>Invoice</th><th>Status</th><th>Voucher</th><th>File</th><th>Duration</th><th>Error</th></tr>\n");
+        for entry in &self.entries {
+            let _ = writeln!(out, "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}ms</td><td>{}</td></tr>",
+                             status_name(entry.status),
+                             escape_html(&entry.profile),
+                             escape_html(&entry.invoice_number),
+                             status_name(entry.status),
+                             escape_html(entry.voucher_id.as_deref().unwrap_or_default()),
+                             escape_html(entry.file.as_deref().unwrap_or_default()),
+                             entry.duration_ms,
+                             escape_html(entry.error.as_deref().unwrap_or_default()));
+        }
+        out.push_str("</table>\n</body></html>\n");
+        out
+    }
+}
+
+fn status_name(status: EntryStatus) -> &'static str {
+    match status {
+        EntryStatus::Uploaded => "uploaded",
+        EntryStatus::Failed => "failed",
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Aligns the cells in columns separated by `|`, with a dashed line below the headers.
+pub fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| cells.iter().zip(&widths)
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", line(headers.to_vec()).trim_end());
+    let _ = writeln!(out, "{}", widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>().join("-+-"));
+    for row in rows {
+        let _ = writeln!(out, "{}", line(row.iter().map(String::as_str).collect()).trim_end());
+    }
+    out
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use crate::error::{Error, Result};
+use crate::invoice::invoice::InvoiceCSV;
+use crate::report::render_table;
+use crate::state::{InvoiceState, StateStore};
+
+/// A column the invoices are grouped by.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dimension {
+    /// month of the invoice date, `YYYY-MM`
+    Month,
+    Prefix,
+    /// name of the billing address, the whole address if it has no name
+    Customer,
+    Currency,
+    TaxRate,
+    /// `uploaded`, `failed` or `pending`, see `upload_status`, or `UNROUTED`
+    Status,
+}
+
+impl Dimension {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dimension::Month => "month",
+            Dimension::Prefix => "prefix",
+            Dimension::Customer => "customer",
+            Dimension::Currency => "currency",
+            Dimension::TaxRate => "tax-rate",
+            Dimension::Status => "status",
+        }
+    }
+
+    fn value(&self, invoice: &InvoiceCSV, status: &str) -> String {
+        match self {
+            Dimension::Month => invoice.invoice_date().format("%Y-%m").to_string(),
+            Dimension::Prefix => invoice.prefix(),
+            Dimension::Customer => invoice.parsed_billing_address().name
+                .unwrap_or_else(|| invoice.billing_address().trim().to_string()),
+            Dimension::Currency => invoice.currency().to_string(),
+            Dimension::TaxRate => format!("{}%", invoice.vat_rate().normalize()),
+            Dimension::Status => status.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        serde_yaml::from_value(serde_yaml::Value::from(name))
+            .map_err(|_| format!("unknown dimension {}, expected month, prefix, customer, currency, tax-rate or status", name))
+    }
+}
+
+/// Sums of one group, `key` holds the values of the dimensions in their order.
+#[derive(Serialize, Debug, Clone)]
+pub struct RevenueRow {
+    pub key: Vec<String>,
+    pub invoices: usize,
+    pub net: Decimal,
+    pub tax: Decimal,
+    pub gross: Decimal,
+}
+
+impl RevenueRow {
+    fn new(key: Vec<String>) -> Self {
+        Self { key, invoices: 0, net: Decimal::ZERO, tax: Decimal::ZERO, gross: Decimal::ZERO }
+    }
+
+    fn add(&mut self, invoice: &InvoiceCSV) {
+        self.invoices += 1;
+        self.net += invoice.net();
+        self.tax += invoice.final_amount() - invoice.net();
+        self.gross += invoice.final_amount();
+    }
+
+    fn cells(&self) -> Vec<String> {
+        let mut cells = self.key.clone();
+        cells.extend([self.invoices.to_string(), self.net.round_dp(2).to_string(), self.tax.round_dp(2).to_string(),
+                      self.gross.round_dp(2).to_string()]);
+        cells
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RevenueReport {
+    pub dimensions: Vec<Dimension>,
+    /// sorted by key
+    pub rows: Vec<RevenueRow>,
+    /// one row per currency, amounts in different currencies are never added up
+    pub totals: Vec<RevenueRow>,
+}
+
+/// Status of the invoices no profile handles with `--all-profiles`, they have no state database.
+pub const UNROUTED: &str = "unrouted";
+
+/// Upload status of an invoice in the state database of its profile.
+pub fn upload_status(state: &StateStore, invoice_number: &str) -> Result<&'static str> {
+    Ok(match state.get(invoice_number)?.map(|record| record.state) {
+        Some(InvoiceState::FileUploaded) => "uploaded",
+        Some(InvoiceState::Failed) => "failed",
+        _ => "pending",
+    })
+}
+
+/// Groups the invoices, each given with its upload status, by `dimensions` and sums their amounts.
+pub fn summarize(dimensions: &[Dimension], invoices: &[(&InvoiceCSV, &str)]) -> RevenueReport {
+    let mut groups: BTreeMap<Vec<String>, RevenueRow> = BTreeMap::new();
+    let mut totals: BTreeMap<String, RevenueRow> = BTreeMap::new();
+    for (invoice, status) in invoices {
+        let key: Vec<String> = dimensions.iter().map(|dimension| dimension.value(invoice, status)).collect();
+        groups.entry(key.clone()).or_insert_with(|| RevenueRow::new(key)).add(invoice);
+        totals.entry(invoice.currency().to_string())
+            .or_insert_with(|| RevenueRow::new(vec![invoice.currency().to_string()]))
+            .add(invoice);
+    }
+    RevenueReport {
+        dimensions: dimensions.to_vec(),
+        rows: groups.into_values().collect(),
+        totals: totals.into_values().collect(),
+    }
+}
+
+fn headers(report: &RevenueReport) -> Vec<&'static str> {
+    let mut headers: Vec<&str> = report.dimensions.iter().map(Dimension::name).collect();
+    headers.extend(["invoices", "net", "tax", "gross"]);
+    headers
+}
+
+/// The groups as a table followed by a total line per currency.
+pub fn render_revenue(report: &RevenueReport) -> String {
+    let mut rows: Vec<Vec<String>> = report.rows.iter().map(RevenueRow::cells).collect();
+    for total in &report.totals {
+        let mut key = vec![String::new(); report.dimensions.len()];
+        if let Some(first) = key.first_mut() {
+            *first = format!("total {}", total.key[0]);
+        }
+        rows.push(RevenueRow { key, ..total.clone() }.cells());
+    }
+    render_table(&headers(report), &rows)
+}
+
+pub fn write_csv(report: &RevenueReport, path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_path(path).map_err(|e| Error::csv(path, e))?;
+    wtr.write_record(headers(report)).map_err(|e| Error::csv(path, e))?;
+    for row in &report.rows {
+        wtr.write_record(row.cells()).map_err(|e| Error::csv(path, e))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn write_json(report: &RevenueReport, path: &str) -> Result<()> {
+    let json = serde_json::to_string_pretty(report).map_err(|e| Error::json(path, e))?;
+    fs::write(path, json)?;
+    Ok(())
+}
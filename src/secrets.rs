@@ -0,0 +1,160 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use crate::error::{Error, Result};
+
+
+// header written in front of every encrypted secrets file, bump it if the layout changes
+const MAGIC: &[u8] = b"LEXSEC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Reads a plain text key file, refusing files that are readable by other users.
+pub fn read_key_file(path: &str) -> Result<String> {
+    check_permissions(Path::new(path))?;
+    let content = fs::read_to_string(path)?;
+    Ok(content.trim().to_string())
+}
+
+/// Decrypts the secrets file at `path` with the given passphrase.
+pub fn read_encrypted_key_file(path: &str, passphrase: &str) -> Result<String> {
+    check_permissions(Path::new(path))?;
+    let data = fs::read(path)?;
+    decrypt(&data, passphrase)
+}
+
+/// Encrypts `secret` with the passphrase and stores it at `path`, readable only by the owner.
+pub fn write_encrypted_key_file(path: &str, secret: &str, passphrase: &str) -> Result<()> {
+    let data = encrypt(secret, passphrase)?;
+    let mut file = create_private_file(Path::new(path))?;
+    file.write_all(&data)?;
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::Secrets(format!("Could not derive key from passphrase: {}", e)))?;
+    Ok(key)
+}
+
+fn encrypt(secret: &str, passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, secret.as_bytes())
+        .map_err(|_| Error::Secrets("Could not encrypt secret".to_string()))?;
+
+    let mut data = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+fn decrypt(data: &[u8], passphrase: &str) -> Result<String> {
+    if data.len() < MAGIC.len() + SALT_LEN + NONCE_LEN || !data.starts_with(MAGIC) {
+        return Err(Error::Secrets("Not a valid secrets file!".to_string()));
+    }
+    let (salt, rest) = data[MAGIC.len()..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::Secrets("Wrong passphrase or corrupted secrets file!".to_string()))?;
+    String::from_utf8(plaintext).map_err(|_| Error::Secrets("The secrets file does not contain text".to_string()))
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(Error::Secrets(format!("{} is accessible by other users, restrict it with `chmod 600 {}`",
+                           path.display(), path.display())));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(path: &Path) -> Result<()> {
+    // windows ACLs are not inspected, the file only has to exist
+    fs::metadata(path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> Result<fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // the mode above only applies to new files, tighten existing ones as well
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> Result<fs::File> {
+    Ok(fs::File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = encrypt("secret-api-key", "passphrase").expect("encrypts");
+        assert!(data.starts_with(MAGIC));
+        assert_eq!(decrypt(&data, "passphrase").expect("decrypts"), "secret-api-key");
+    }
+
+    #[test]
+    fn wrong_passphrase_is_an_error() {
+        let data = encrypt("secret-api-key", "passphrase").expect("encrypts");
+        assert!(matches!(decrypt(&data, "other passphrase"), Err(Error::Secrets(_))));
+    }
+
+    #[test]
+    fn truncated_and_foreign_files_are_errors() {
+        let data = encrypt("secret-api-key", "passphrase").expect("encrypts");
+        for length in [0, MAGIC.len(), MAGIC.len() + SALT_LEN, MAGIC.len() + SALT_LEN + NONCE_LEN, data.len() - 1] {
+            assert!(matches!(decrypt(&data[..length], "passphrase"), Err(Error::Secrets(_))), "length {}", length);
+        }
+        assert!(matches!(decrypt(b"api_key: plain text yaml, no secrets file", "passphrase"), Err(Error::Secrets(_))));
+    }
+
+    #[test]
+    fn written_file_can_be_read() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("secrets.bin");
+        let path = path.to_str().expect("utf-8 path");
+        write_encrypted_key_file(path, "secret-api-key", "passphrase").expect("writes");
+        assert_eq!(read_encrypted_key_file(path, "passphrase").expect("reads"), "secret-api-key");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_files_readable_by_others_are_rejected() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("api_key.txt");
+        fs::write(&path, "secret-api-key\n").expect("writes");
+        let path_str = path.to_str().expect("utf-8 path");
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).expect("chmod");
+        assert!(matches!(read_key_file(path_str), Err(Error::Secrets(_))));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).expect("chmod");
+        assert_eq!(read_key_file(path_str).expect("private file is read"), "secret-api-key");
+    }
+}
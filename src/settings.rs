@@ -1,9 +1,34 @@
-use log::{info};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use chrono::{Datelike, Days, NaiveDate};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
-use text_io::read;
-use confy;
-use confy::ConfyError;
 use uuid::Uuid;
+use crate::migration::{self, CURRENT_VERSION};
+use crate::error::{Error, MappingKind, Result};
+use crate::invoice::invoice::InvoiceCSV;
+use crate::logging;
+use crate::secrets;
+use crate::tax::{self, TaxCase, TaxCaseConfig};
+
+/// environment variable that takes precedence over every other api key source
+pub const API_KEY_ENV: &str = "LEXOFFICE_API_KEY";
+/// environment variable used to unlock the encrypted secrets file without a prompt
+pub const PASSPHRASE_ENV: &str = "LEXOFFICE_SECRETS_PASSPHRASE";
+pub const CONFIG_PATH: &str = "lexUploadConfig.yaml";
+/// ledger of uploaded invoices used when no other path is configured
+pub const DEFAULT_LEDGER_PATH: &str = "done_invoices.csv";
+/// invoices that failed in the last runs, see `failures`
+pub const DEFAULT_FAILURES_PATH: &str = "failed_invoices.json";
+/// name of the top level settings in logs, the state database and file names, no profile may use it
+pub const DEFAULT_PROFILE_NAME: &str = "default";
+/// sqlite database with the upload state of every invoice of all profiles, see `state`
+pub const STATE_PATH: &str = "lexUploadState.db";
+/// longest accepted `net` payment terms in days
+pub const MAX_PAYMENT_DAYS: u64 = 365;
+/// how often a rejected api key may be re-entered during one run
+pub const MAX_API_KEY_PROMPTS: u32 = 3;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PrefixConfig {
@@ -15,178 +40,763 @@ pub struct PrefixConfig {
 pub struct Customer{
     pub customer_id: String,
     pub customer_adress: String,
+    /// overrides the payment terms of the config for this customer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_terms: Option<PaymentTerms>,
+}
+
+/// When an invoice is due, written as `net 14`, `net 30` or `end of month` in the config.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum PaymentTerms {
+    /// due the given number of days after the invoice date
+    Net(u64),
+    /// due on the last day of the month of the invoice date
+    EndOfMonth,
+}
+
+impl PaymentTerms {
+    /// Fails only if the due date is beyond the dates chrono can represent.
+    pub fn due_date(&self, invoice_date: NaiveDate) -> Result<NaiveDate> {
+        match self {
+            PaymentTerms::Net(days) => invoice_date.checked_add_days(Days::new(*days))
+                .ok_or_else(|| Error::Config(format!("payment terms {} overflow the invoice date {}", self, invoice_date))),
+            PaymentTerms::EndOfMonth => {
+                let first_of_next = match invoice_date.month() {
+                    12 => NaiveDate::from_ymd_opt(invoice_date.year() + 1, 1, 1),
+                    month => NaiveDate::from_ymd_opt(invoice_date.year(), month + 1, 1),
+                };
+                Ok(first_of_next.and_then(|date| date.pred_opt()).unwrap_or(invoice_date))
+            }
+        }
+    }
+}
+
+impl FromStr for PaymentTerms {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let terms = s.trim().to_lowercase();
+        if terms == "end of month" || terms == "eom" {
+            return Ok(PaymentTerms::EndOfMonth);
+        }
+        let days: u64 = terms.strip_prefix("net")
+            .and_then(|days| days.trim().parse().ok())
+            .ok_or_else(|| format!("unknown payment terms {}, expected e.g. net 14 or end of month", s))?;
+        if days > MAX_PAYMENT_DAYS {
+            return Err(format!("payment terms {} exceed {} days", s, MAX_PAYMENT_DAYS));
+        }
+        Ok(PaymentTerms::Net(days))
+    }
+}
+
+impl TryFrom<String> for PaymentTerms {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PaymentTerms> for String {
+    fn from(terms: PaymentTerms) -> Self {
+        terms.to_string()
+    }
+}
+
+impl fmt::Display for PaymentTerms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentTerms::Net(days) => write!(f, "net {}", days),
+            PaymentTerms::EndOfMonth => f.write_str("end of month"),
+        }
+    }
+}
+
+/// Books invoices of a transaction type (e.g. b2b) in one tax case to a specific lexoffice category.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CategoryConfig {
+    pub transaction_type: String,
+    /// entries without a case, written by older versions, are not used and reported by `config validate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case: Option<TaxCase>,
+    pub category_id: String,
+}
+
+/// Settings of one lexoffice organization, selected with `--profile` or by invoice prefix.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<ApiKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_encrypted_file: Option<String>,
+    pub prefixes: Option<Vec<PrefixConfig>>,
+    pub customers: Option<Vec<Customer>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub categories: Option<Vec<CategoryConfig>>,
+    /// defaults to done_invoices_<name>.csv
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_terms: Option<PaymentTerms>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub home_country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_cases: Option<Vec<TaxCaseConfig>>,
+}
+
+/// Wrapper around the lexoffice api key, never printed in full by `Debug`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new(key: String) -> Self {
+        Self(key.trim().to_string())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Masked representation, keeps only the last four characters.
+    pub fn masked(&self) -> String {
+        let length = self.0.chars().count();
+        if length <= 8 {
+            return "****".to_string();
+        }
+        format!("****{}", self.0.chars().skip(length - 4).collect::<String>())
+    }
 }
 
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey({})", self.masked())
+    }
+}
+
+/// Where the api key in use was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ApiKeySource {
+    #[default]
+    Config,
+    Environment,
+    File,
+    EncryptedFile,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
-    pub api_key: String,
+    /// schema version of the file, see `migration::CURRENT_VERSION`
+    #[serde(default)]
+    pub version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<ApiKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_encrypted_file: Option<String>,
     pub prefixes: Option<Vec<PrefixConfig>>,
     pub customers: Option<Vec<Customer>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub categories: Option<Vec<CategoryConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<String>,
+    /// default payment terms, invoices have no due date if neither these nor the customer's terms are set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_terms: Option<PaymentTerms>,
+    /// template of the voucher remark, see `invoice::REMARK_PLACEHOLDERS`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+    /// ISO code of the seller's country, `tax::DEFAULT_HOME_COUNTRY` if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub home_country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_cases: Option<Vec<TaxCaseConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<Profile>>,
+    #[serde(skip)]
+    active_profile: Option<String>,
+    #[serde(skip)]
+    active_key: ApiKey,
+    #[serde(skip)]
+    key_source: ApiKeySource,
+    #[serde(skip)]
+    non_interactive: bool,
+    #[serde(skip)]
+    api_key_prompts: u32,
 }
 
 impl Config {
     pub fn validate(&self) -> bool {
-        return self.api_key.len() > 15;
+        self.active_key.expose().len() > 15
+    }
+
+    /// The api key used for requests, resolved by `resolve_api_key`.
+    pub fn api_key(&self) -> &ApiKey {
+        &self.active_key
+    }
+
+    /// Name of the profile this config was built from, `None` for the top level settings.
+    pub fn profile_name(&self) -> Option<&str> {
+        self.active_profile.as_deref()
+    }
+
+    /// Human readable name for log output.
+    pub fn display_name(&self) -> &str {
+        self.profile_name().unwrap_or(DEFAULT_PROFILE_NAME)
+    }
+
+    /// Path of the done invoices ledger of this config.
+    /// It is imported into the state database on first use and written by `state export`.
+    pub fn ledger_path(&self) -> String {
+        match (&self.ledger, &self.active_profile) {
+            (Some(ledger), _) => ledger.clone(),
+            (None, Some(name)) => format!("done_invoices_{}.csv", name),
+            (None, None) => DEFAULT_LEDGER_PATH.to_string(),
+        }
+    }
+
+    /// Path of the file listing the invoices whose last upload failed.
+    pub fn failures_path(&self) -> String {
+        match &self.active_profile {
+            Some(name) => format!("failed_invoices_{}.json", name),
+            None => DEFAULT_FAILURES_PATH.to_string(),
+        }
+    }
+
+    /// The category configured for invoices of the transaction type in the tax case, if any.
+    pub fn transaction_category(&self, transaction_type: &str, case: TaxCase) -> Option<&str> {
+        self.categories.iter().flatten()
+            .find(|category| category.transaction_type == transaction_type && category.case == Some(case))
+            .map(|category| category.category_id.as_str())
+    }
+
+    /// ISO code of the seller's country, invoices to it are domestic.
+    pub fn home_country(&self) -> &str {
+        self.home_country.as_deref().unwrap_or(tax::DEFAULT_HOME_COUNTRY)
+    }
+
+    /// The configured overrides of a tax case.
+    pub fn tax_case(&self, case: TaxCase) -> Option<&TaxCaseConfig> {
+        self.tax_cases.iter().flatten().find(|config| config.case == case)
+    }
+
+    /// Payment terms of the customer with this address, falling back to the terms of the config.
+    pub fn payment_terms(&self, address: &str) -> Option<PaymentTerms> {
+        self.customers.iter().flatten()
+            .find(|customer| customer.customer_adress == address)
+            .and_then(|customer| customer.payment_terms)
+            .or(self.payment_terms)
+    }
+
+    /// Whether invoices with this prefix belong to this config.
+    pub fn has_prefix(&self, prefix: &str) -> bool {
+        self.prefixes.iter().flatten().any(|prefix_config| prefix_config.prefix == prefix)
+    }
+
+    /// Builds the config of the named profile, or returns the top level settings for `None`.
+    pub fn select_profile(&self, name: Option<&str>) -> Result<Config> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(self.clone_root()),
+        };
+        let profile = self.profiles.iter().flatten()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| Error::config(format!("Profile {} does not exist in {}", name, CONFIG_PATH)))?;
+        Ok(Config::from_profile(profile))
+    }
+
+    /// Configs of all named profiles, used to route invoices by prefix.
+    pub fn profile_configs(&self) -> Vec<Config> {
+        self.profiles.iter().flatten().map(Config::from_profile).collect()
+    }
+
+    /// The named profile or the top level settings, or with `all_profiles` every named profile.
+    pub fn select_configs(&self, profile: Option<&str>, all_profiles: bool) -> Result<Vec<Config>> {
+        if !all_profiles {
+            return Ok(vec![self.select_profile(profile)?]);
+        }
+        let configs = self.profile_configs();
+        if configs.is_empty() {
+            return Err(Error::Config("No profiles are configured, --all-profiles needs at least one profile".to_string()));
+        }
+        Ok(configs)
+    }
+
+    fn from_profile(profile: &Profile) -> Config {
+        Config {
+            version: CURRENT_VERSION,
+            api_key: profile.api_key.clone(),
+            api_key_file: profile.api_key_file.clone(),
+            api_key_encrypted_file: profile.api_key_encrypted_file.clone(),
+            prefixes: Some(profile.prefixes.clone().unwrap_or_default()),
+            customers: Some(profile.customers.clone().unwrap_or_default()),
+            categories: profile.categories.clone(),
+            ledger: profile.ledger.clone(),
+            payment_terms: profile.payment_terms,
+            remark: profile.remark.clone(),
+            home_country: profile.home_country.clone(),
+            tax_cases: profile.tax_cases.clone(),
+            profiles: None,
+            active_profile: Some(profile.name.clone()),
+            active_key: ApiKey::default(),
+            key_source: ApiKeySource::default(),
+            non_interactive: false,
+            api_key_prompts: 0,
+        }
+    }
+
+    fn clone_root(&self) -> Config {
+        Config {
+            version: self.version,
+            api_key: self.api_key.clone(),
+            api_key_file: self.api_key_file.clone(),
+            api_key_encrypted_file: self.api_key_encrypted_file.clone(),
+            prefixes: self.prefixes.clone(),
+            customers: self.customers.clone(),
+            categories: self.categories.clone(),
+            ledger: self.ledger.clone(),
+            payment_terms: self.payment_terms,
+            remark: self.remark.clone(),
+            home_country: self.home_country.clone(),
+            tax_cases: self.tax_cases.clone(),
+            profiles: self.profiles.clone(),
+            active_profile: None,
+            active_key: ApiKey::default(),
+            key_source: ApiKeySource::default(),
+            non_interactive: false,
+            api_key_prompts: 0,
+        }
+    }
+
+    /// Writes this config back to lexUploadConfig.yaml.
+    /// A profile config only replaces its own profile entry, everything else is kept as is.
+    pub fn store(&self) -> Result<()> {
+        let name = match &self.active_profile {
+            Some(name) => name,
+            None => return Ok(confy::store_path(CONFIG_PATH, self)?),
+        };
+
+        let mut root: Config = confy::load_path(CONFIG_PATH)?;
+        let mut profiles = root.profiles.take().unwrap_or_default();
+        let profile = match profiles.iter_mut().find(|profile| &profile.name == name) {
+            Some(profile) => profile,
+            None => {
+                profiles.push(Profile { name: name.clone(), ..Profile::default() });
+                profiles.last_mut().unwrap()
+            }
+        };
+        profile.api_key = self.api_key.clone();
+        profile.api_key_file = self.api_key_file.clone();
+        profile.api_key_encrypted_file = self.api_key_encrypted_file.clone();
+        profile.prefixes = self.prefixes.clone();
+        profile.customers = self.customers.clone();
+        profile.categories = self.categories.clone();
+        profile.ledger = self.ledger.clone();
+        profile.payment_terms = self.payment_terms;
+        profile.remark = self.remark.clone();
+        profile.home_country = self.home_country.clone();
+        profile.tax_cases = self.tax_cases.clone();
+        root.profiles = Some(profiles);
+        Ok(confy::store_path(CONFIG_PATH, root)?)
+    }
+
+    /// Resolves the api key in the order environment, key file, encrypted key file, config file.
+    /// Profiles read the environment variable suffixed with their upper case name,
+    /// e.g. LEXOFFICE_API_KEY_SHOP for the profile `shop`.
+    pub fn resolve_api_key(&mut self) -> Result<()> {
+        let env_name = match &self.active_profile {
+            Some(name) => format!("{}_{}", API_KEY_ENV, name.to_uppercase().replace('-', "_")),
+            None => API_KEY_ENV.to_string(),
+        };
+        if let Ok(key) = env::var(&env_name) {
+            if !key.trim().is_empty() {
+                info!("Using api key from environment variable {}", env_name);
+                return self.set_active_key(ApiKey::new(key), ApiKeySource::Environment);
+            }
+        }
+
+        if let Some(path) = &self.api_key_file {
+            info!("Using api key from file {}", path);
+            let key = secrets::read_key_file(path)?;
+            return self.set_active_key(ApiKey::new(key), ApiKeySource::File);
+        }
+
+        if let Some(path) = &self.api_key_encrypted_file {
+            info!("Using api key from encrypted file {}", path);
+            let passphrase = read_passphrase()?;
+            let key = secrets::read_encrypted_key_file(path, &passphrase)?;
+            return self.set_active_key(ApiKey::new(key), ApiKeySource::EncryptedFile);
+        }
+
+        let key = self.api_key.clone().unwrap_or_default();
+        if !key.expose().is_empty() {
+            warn!("The api key is stored in plain text in {}, consider using {} or an encrypted key file", CONFIG_PATH, env_name);
+        }
+        self.set_active_key(key, ApiKeySource::Config)
+    }
+
+    /// Disables all prompts, missing input is reported as an error instead.
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.non_interactive = !interactive;
+    }
+
+    fn set_active_key(&mut self, key: ApiKey, source: ApiKeySource) -> Result<()> {
+        logging::redact(key.expose());
+        self.active_key = key;
+        self.key_source = source;
+        Ok(())
     }
 }
 
 impl Default for Config {
-    fn default() -> Self { Self { api_key: update_api_key(), prefixes: Some(vec![]), customers: Some(vec![]) } }
+    fn default() -> Self {
+        // with the key provided by the environment there is nothing to ask for
+        let api_key = match env::var(API_KEY_ENV) {
+            Ok(_) => None,
+            Err(_) => update_api_key().ok(),
+        };
+        Self {
+            version: CURRENT_VERSION,
+            api_key,
+            api_key_file: None,
+            api_key_encrypted_file: None,
+            prefixes: Some(vec![]),
+            customers: Some(vec![]),
+            categories: None,
+            ledger: None,
+            payment_terms: None,
+            remark: None,
+            home_country: None,
+            tax_cases: None,
+            profiles: None,
+            active_profile: None,
+            active_key: ApiKey::default(),
+            key_source: ApiKeySource::default(),
+            non_interactive: false,
+            api_key_prompts: 0,
+        }
+    }
 }
 
-pub fn load_settings() -> Result<Config, confy::ConfyError> {
-    let mut cfg: Config = confy::load_path("lexUploadConfig.yaml")?;
+/// lexoffice contact ids are uuids, anything else is a typo.
+pub fn is_valid_customer_id(customer_id: &str) -> bool {
+    Uuid::parse_str(customer_id).is_ok()
+}
+
+/// Reads one line from stdin without the line break, end of input is an error.
+fn read_line() -> Result<String> {
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Err(Error::Io(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stdin was closed")));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_passphrase() -> std::io::Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    rpassword::prompt_password("Please enter the passphrase for the encrypted api key file: ")
+}
+
+pub fn load_settings() -> Result<Config> {
+    migration::migrate_config_file(CONFIG_PATH)?;
+    let mut cfg: Config = confy::load_path(CONFIG_PATH)?;
 
     // initializes the prefixes if they are empty
-    init_prefixes(&mut cfg).expect("Failed to initialize prefixes");
-    init_customers(&mut cfg).expect("Failed to initialize customers");
+    if cfg.profiles.iter().flatten().any(|profile| profile.name == DEFAULT_PROFILE_NAME) {
+        return Err(Error::config(format!("The profile name {} is reserved for the top level settings, rename the profile",
+                                         DEFAULT_PROFILE_NAME)));
+    }
+    init_prefixes(&mut cfg)?;
+    init_customers(&mut cfg)?;
+    let profile_customers = cfg.profiles.iter().flatten().flat_map(|profile| profile.customers.iter().flatten());
+    for customer in cfg.customers.iter().flatten().chain(profile_customers) {
+        logging::redact_address(&customer.customer_adress);
+    }
     Ok(cfg)
 }
 
-fn init_prefixes(cfg: &mut Config) -> Result<(), confy::ConfyError> {
+fn init_prefixes(cfg: &mut Config) -> Result<()> {
     if cfg.prefixes.is_none() {
         cfg.prefixes = Some(vec![]);
     }
-    confy::store_path("lexUploadConfig.yaml", &cfg)
+    cfg.store()
 }
 
-fn init_customers(cfg: &mut Config) -> Result<(), confy::ConfyError> {
+fn init_customers(cfg: &mut Config) -> Result<()> {
     if cfg.customers.is_none() {
         cfg.customers = Some(vec![]);
     }
-    confy::store_path("lexUploadConfig.yaml", &cfg)
+    cfg.store()
 }
 
-pub fn update_settings(){
-    let new_config = Config {
-        api_key: update_api_key(),
-        prefixes: Some(vec![]),
-        customers: Some(vec![]),
+fn update_api_key() -> Result<ApiKey> {
+    info!("Getting api key from user!");
+    let api_key = rpassword::prompt_password("Please enter your API KEY and confirm with enter: ")?;
+    let api_key = ApiKey::new(api_key);
+    println!("Read api key {}", api_key.masked());
+    Ok(api_key)
+}
 
-    };
+/// Prompts for the api key and a passphrase and writes the encrypted secrets file.
+/// The config is switched to the encrypted file and the plain text key is removed from it.
+pub fn encrypt_api_key(output: &str, profile: Option<&str>) -> Result<()> {
+    let api_key = update_api_key()?;
+    let passphrase = rpassword::prompt_password("Please enter a passphrase for the secrets file: ")?;
+    let confirmation = rpassword::prompt_password("Please repeat the passphrase: ")?;
+    if passphrase != confirmation {
+        return Err(Error::Secrets("The passphrases do not match!".to_string()));
+    }
+    if passphrase.is_empty() {
+        return Err(Error::Secrets("The passphrase must not be empty!".to_string()));
+    }
 
-    confy::store_path("lexUploadConfig.yaml", new_config).unwrap();
-}
+    secrets::write_encrypted_key_file(output, api_key.expose(), &passphrase)?;
+    info!("Wrote encrypted api key to {}", output);
 
-fn update_api_key() -> String {
-    info!("Getting api key from user!");
-    println!("Please enter your API KEY and confirm with enter: ");
-    let api_key: String = read!("{}\n");
-    println!("You entered: {}", api_key);
-    api_key
-}
-
-pub fn get_prefix_path(prefix: String) -> Result<String, ConfyError> {
-    let mut config = load_settings()?;
-    println!("Got a new Path: {}. \n Please enter the corresponding Folder (e.g. alias) for \
-        the alias folder", prefix);
-
-    let user_input: String = read!("{}\n");
-    let result: Result<String, ConfyError> = Ok(user_input.clone());
-    // add the user input to the path lists
-    let new_prefix = PrefixConfig {
-        prefix,
-        path: user_input,
-    };
-    let mut prefixes = match config.prefixes {
-        Some(prefixes) => prefixes,
-        None => vec![],
-    };
-    prefixes.push(new_prefix);
-    config.prefixes = Some(prefixes);
-    confy::store_path("lexUploadConfig.yaml", config)?;
-    result
-}
-
-fn get_customer_id(adress: &String) -> Result<String, ConfyError> {
-    let mut config = load_settings()?;
-    println!("Got a new Adress: {}. \n Please enter the corresponding Customer id from lexoffice", adress);
-
-    let user_input: String = read!("{}\n");
-    let result: Result<String, ConfyError> = Ok(user_input.clone());
-
-    // validate the input is a uuid v4
-    let uuid = Uuid::parse_str(&user_input);
-    if uuid.is_err() {
-        println!("The input is not a valid uuid v4. Please try again!");
-        return get_customer_id(adress);
-    }
-
-    // add the user input to the path lists
-    let new_customer = Customer {
-        customer_id: user_input,
-        customer_adress: adress.clone(),
-    };
-    let mut customers = match config.customers {
-        Some(customers) => customers,
-        None => vec![],
-    };
-    customers.push(new_customer);
-    config.customers = Some(customers);
-    confy::store_path("lexUploadConfig.yaml", config)?;
-    result
+    let mut config = load_settings()?.select_profile(profile)?;
+    config.api_key = None;
+    config.api_key_file = None;
+    config.api_key_encrypted_file = Some(output.to_string());
+    config.store()?;
+    info!("Updated {} profile to use the encrypted api key file", config.display_name());
+    Ok(())
 }
 
 impl Config {
-    pub fn get_path(&mut self, prefix: &str) -> Result<String, ConfyError> {
-        let prefixes = match &self.prefixes {
-            Some(prefixes) => prefixes,
-            None => {
-                init_prefixes(self).expect("Failed to initialize prefixes");
-                get_prefix_path(prefix.to_string()).expect("Failed to get path");
-                // update self with new prefixes
-                let new_config = load_settings().expect("Failed to load new config");
-                self.prefixes = new_config.prefixes.clone();
-                self.prefixes.as_ref().unwrap()
-            },
-        };
+    fn prompt_prefix_path(&mut self, prefix: &str) -> Result<String> {
+        if self.non_interactive {
+            return Err(Error::MappingMissing { kind: MappingKind::Prefix, key: prefix.to_string() });
+        }
+        println!("Got a new Path: {} ({} profile). \n Please enter the corresponding Folder (e.g. alias) for \
+            the alias folder", prefix, self.display_name());
 
-        for prefix_config in prefixes {
-            if prefix_config.prefix == prefix {
-                return Ok(prefix_config.path.clone());
-            }
+        let user_input = read_line()?;
+        // add the user input to the path lists
+        self.prefixes.get_or_insert_with(Vec::new).push(PrefixConfig {
+            prefix: prefix.to_string(),
+            path: user_input.clone(),
+        });
+        self.store()?;
+        Ok(user_input)
+    }
+
+    fn prompt_customer_id(&mut self, adress: &str) -> Result<String> {
+        if self.non_interactive {
+            return Err(Error::MappingMissing { kind: MappingKind::Customer, key: adress.to_string() });
+        }
+        println!("Got a new Adress: {} ({} profile). \n Please enter the corresponding Customer id from lexoffice", adress, self.display_name());
+
+        let user_input = read_line()?;
+
+        // validate the input is a uuid v4
+        if !is_valid_customer_id(&user_input) {
+            println!("The input is not a valid uuid v4. Please try again!");
+            return self.prompt_customer_id(adress);
         }
 
-        get_prefix_path(prefix.to_string()).expect("Failed to get path");
-        // update self with new prefixes
-        let new_config = load_settings().expect("Failed to load new config");
-        self.prefixes = new_config.prefixes;
-        // recursive call to get the path to simplify the code - should not be infinite
-        self.get_path(prefix)
+        // add the user input to the customer list
+        self.customers.get_or_insert_with(Vec::new).push(Customer {
+            customer_id: user_input.clone(),
+            customer_adress: adress.to_string(),
+            payment_terms: None,
+        });
+        self.store()?;
+        Ok(user_input)
+    }
 
+    pub fn get_path(&mut self, prefix: &str) -> Result<String> {
+        match self.prefix_path(prefix) {
+            Some(path) => Ok(path.to_string()),
+            None => self.prompt_prefix_path(prefix),
+        }
     }
 
-    pub fn get_customer_id(&mut self, address: &String) -> Result<String, ConfyError> {
-        let ids = match &self.customers {
-            Some(customers) => customers,
-            None => {
-                init_customers(self).expect("Failed to initialize customers");
-                get_customer_id(address).expect("Failed to get customer id");
-                // update self with new prefixes
-                let new_config = load_settings().expect("Failed to load new config");
-                self.customers = new_config.customers.clone();
-                self.customers.as_ref().unwrap()
-            },
+    /// The folder mapped to the prefix, without asking for it.
+    pub fn prefix_path(&self, prefix: &str) -> Option<&str> {
+        self.prefixes.iter().flatten()
+            .find(|prefix_config| prefix_config.prefix == prefix)
+            .map(|prefix_config| prefix_config.path.as_str())
+    }
+
+    pub fn get_customer_id(&mut self, address: &str) -> Result<String> {
+        match self.customer_id(address) {
+            Some(customer_id) => Ok(customer_id.to_string()),
+            // customer id is not in the list
+            None => self.prompt_customer_id(address),
+        }
+    }
+
+    /// The contact id mapped to the address, without asking for it.
+    pub fn customer_id(&self, address: &str) -> Option<&str> {
+        self.customers.iter().flatten()
+            .find(|customer| customer.customer_adress == address)
+            .map(|customer| customer.customer_id.as_str())
+    }
+
+    /// Asks the user for a new api key after the current one was rejected.
+    /// Returns false if no new key can be obtained, either because the run is non-interactive
+    /// or because the key was already re-entered `MAX_API_KEY_PROMPTS` times.
+    pub fn invalidate_api_key(&mut self) -> bool {
+        if self.non_interactive {
+            error!("The api key is invalid and the run is non-interactive, aborting");
+            return false;
+        }
+        if self.api_key_prompts >= MAX_API_KEY_PROMPTS {
+            error!("The api key was rejected {} times, aborting", self.api_key_prompts);
+            return false;
+        }
+        self.api_key_prompts += 1;
+        let api_key = match update_api_key() {
+            Ok(api_key) => api_key,
+            Err(e) => {
+                error!("Could not read api key: {}", e);
+                return false;
+            }
         };
+        logging::redact(api_key.expose());
+        self.active_key = api_key.clone();
+        if self.key_source != ApiKeySource::Config {
+            // never write a key into the config file that was kept outside of it on purpose
+            warn!("The new api key is only used for this run, please update your {:?} api key source", self.key_source);
+            return true;
+        }
+        self.api_key = Some(api_key);
+        if let Err(e) = self.store() {
+            error!("Failed to store new api key: {}", e);
+        }
+        true
+    }
+}
+
+/// Splits the invoices between the configs, with `all_profiles` every profile only gets the invoices whose
+/// prefix it knows, otherwise the single config gets all of them. Invoices no profile handles are skipped.
+pub fn route(configs: Vec<Config>, invoices: &[InvoiceCSV], all_profiles: bool) -> Vec<(Config, Vec<&InvoiceCSV>)> {
+    let (batches, unrouted) = route_with_unrouted(configs, invoices, all_profiles);
+    for invoice in unrouted {
+        warn!("No profile handles prefix {}, skipping invoice {}", invoice.prefix(), invoice.invoice_number());
+    }
+    batches
+}
 
-        for customer in ids {
-            if &customer.customer_adress == address {
-                return Ok(customer.customer_id.clone());
+/// Like `route`, but also returns the invoices no profile handles instead of skipping them.
+pub fn route_with_unrouted(configs: Vec<Config>, invoices: &[InvoiceCSV], all_profiles: bool)
+                           -> (Vec<(Config, Vec<&InvoiceCSV>)>, Vec<&InvoiceCSV>) {
+    let mut batches: Vec<(Config, Vec<&InvoiceCSV>)> = configs.into_iter().map(|config| (config, vec![])).collect();
+    let mut unrouted = vec![];
+    for invoice in invoices {
+        if !all_profiles {
+            if let Some((_, batch)) = batches.first_mut() {
+                batch.push(invoice);
             }
+            continue;
         }
+        match batches.iter_mut().find(|(config, _)| config.has_prefix(&invoice.prefix())) {
+            Some((_, batch)) => batch.push(invoice),
+            None => unrouted.push(invoice),
+        }
+    }
+    (batches, unrouted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoice::invoice::fixtures;
+
+    fn settings() -> Config {
+        serde_yaml::from_str(&format!("\
+version: {}
+prefixes:
+  - prefix: RE
+    path: /invoices/re
+customers: []
+profiles:
+  - name: shop
+    prefixes:
+      - prefix: AB
+        path: /invoices/ab
+  - name: marketplace
+    prefixes:
+      - prefix: CD
+        path: /invoices/cd
+", CURRENT_VERSION)).expect("valid config")
+    }
+
+    fn numbers<'a>(batch: &[&'a InvoiceCSV]) -> Vec<&'a str> {
+        batch.iter().map(|invoice| invoice.invoice_number()).collect()
+    }
+
+    #[test]
+    fn all_profiles_get_the_invoices_of_their_prefixes() {
+        let invoices = ["AB-1", "CD-1", "AB-2", "XY-1"].map(|number| fixtures::simple(number, "b2c", "X"));
+        let configs = settings().select_configs(None, true).expect("profiles exist");
+        let batches = route(configs, &invoices, true);
+
+        let routed: Vec<(&str, Vec<&str>)> = batches.iter()
+            .map(|(config, batch)| (config.display_name(), numbers(batch)))
+            .collect();
+        assert_eq!(routed, vec![("shop", vec!["AB-1", "AB-2"]), ("marketplace", vec!["CD-1"])]);
+
+        let configs = settings().select_configs(None, true).expect("profiles exist");
+        let (batches, unrouted) = route_with_unrouted(configs, &invoices, true);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(numbers(&unrouted), vec!["XY-1"]);
+    }
+
+    #[test]
+    fn a_single_config_gets_every_invoice() {
+        let invoices = ["AB-1", "XY-1"].map(|number| fixtures::simple(number, "b2c", "X"));
+        let configs = settings().select_configs(Some("shop"), false).expect("profile exists");
+        let batches = route(configs, &invoices, false);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(numbers(&batches[0].1), vec!["AB-1", "XY-1"]);
+
+        let root = settings().select_configs(None, false).expect("top level settings");
+        assert_eq!(root[0].display_name(), DEFAULT_PROFILE_NAME);
+        assert!(settings().select_configs(Some("unknown"), false).is_err());
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).expect("valid date")
+    }
+
+    #[test]
+    fn payment_terms_parse_and_print() {
+        assert_eq!("net 14".parse(), Ok(PaymentTerms::Net(14)));
+        assert_eq!("NET30".parse(), Ok(PaymentTerms::Net(30)));
+        assert_eq!(" net 365 ".parse(), Ok(PaymentTerms::Net(365)));
+        assert_eq!("end of month".parse(), Ok(PaymentTerms::EndOfMonth));
+        assert_eq!("EOM".parse(), Ok(PaymentTerms::EndOfMonth));
+        assert_eq!(PaymentTerms::Net(30).to_string(), "net 30");
+        assert_eq!(PaymentTerms::EndOfMonth.to_string(), "end of month");
+
+        for invalid in ["net 366", "net -1", "net", "30", "net 30 days", "end of week", ""] {
+            assert!(invalid.parse::<PaymentTerms>().is_err(), "{} should be rejected", invalid);
+        }
+    }
 
-        // customer id is not in the list
-        get_customer_id(address).expect("Failed to get path");
-        // update self with new prefixes
-        let new_config = load_settings().expect("Failed to load new config");
-        self.customers = new_config.customers;
-        // recursive call to get the path to simplify the code - should not be infinite
-        self.get_customer_id(address)
+    #[test]
+    fn payment_terms_due_dates() {
+        assert_eq!(PaymentTerms::Net(14).due_date(date(2024, 1, 15)).expect("due date"), date(2024, 1, 29));
+        assert_eq!(PaymentTerms::Net(30).due_date(date(2023, 12, 15)).expect("due date"), date(2024, 1, 14));
+        assert_eq!(PaymentTerms::EndOfMonth.due_date(date(2023, 12, 15)).expect("due date"), date(2023, 12, 31));
+        assert_eq!(PaymentTerms::EndOfMonth.due_date(date(2024, 2, 10)).expect("due date"), date(2024, 2, 29));
+        assert_eq!(PaymentTerms::EndOfMonth.due_date(date(2023, 2, 10)).expect("due date"), date(2023, 2, 28));
+        assert!(matches!(PaymentTerms::Net(MAX_PAYMENT_DAYS).due_date(NaiveDate::MAX), Err(Error::Config(_))));
     }
 
-    pub fn invalidate_api_key(&mut self) {
-        self.api_key = update_api_key();
-        confy::store_path("lexUploadConfig.yaml", self).expect("Failed to store new api key");
+    #[test]
+    fn all_profiles_needs_profiles() {
+        let settings: Config = serde_yaml::from_str("prefixes: []\ncustomers: []\n").expect("valid config");
+        assert!(matches!(settings.select_configs(None, true), Err(Error::Config(_))));
     }
 }
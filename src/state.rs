@@ -0,0 +1,535 @@
+use std::fmt;
+use std::path::Path;
+use chrono::Local;
+use log::info;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::error::{Error, Result};
+use crate::invoice::invoice;
+
+/// Schema version stored in `PRAGMA user_version`.
+const SCHEMA_VERSION: i64 = 2;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS invoices (
+    profile TEXT NOT NULL,
+    invoice_number TEXT NOT NULL,
+    state TEXT NOT NULL,
+    voucher_id TEXT,
+    file_path TEXT,
+    row_hash TEXT,
+    file_hash TEXT,
+    error_category TEXT,
+    error_message TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    first_seen_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    uploaded_at TEXT,
+    correction TEXT,
+    PRIMARY KEY (profile, invoice_number)
+);
+CREATE INDEX IF NOT EXISTS invoices_by_state ON invoices (profile, state);
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    profile TEXT NOT NULL,
+    invoice_number TEXT NOT NULL,
+    state TEXT NOT NULL,
+    message TEXT,
+    at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_by_invoice ON events (profile, invoice_number);
+";
+
+/// Lifecycle of an invoice, in the order it normally passes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InvoiceState {
+    /// found in the csv
+    Seen,
+    /// the voucher could be built from the row and the pdf exists
+    Validated,
+    /// lexoffice accepted the voucher, the pdf is not attached yet
+    VoucherCreated,
+    /// done, the invoice is not uploaded again
+    FileUploaded,
+    Failed,
+    /// a failed invoice is attempted again, only recorded as event
+    Retried,
+    /// the voucher was updated after the csv row changed, only recorded as event
+    Updated,
+    /// the invoice changed after upload and needs a manual correction, only recorded as event
+    Flagged,
+}
+
+impl InvoiceState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            InvoiceState::Seen => "seen",
+            InvoiceState::Validated => "validated",
+            InvoiceState::VoucherCreated => "voucher-created",
+            InvoiceState::FileUploaded => "file-uploaded",
+            InvoiceState::Failed => "failed",
+            InvoiceState::Retried => "retried",
+            InvoiceState::Updated => "updated",
+            InvoiceState::Flagged => "flagged",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        [InvoiceState::Seen, InvoiceState::Validated, InvoiceState::VoucherCreated, InvoiceState::FileUploaded,
+            InvoiceState::Failed, InvoiceState::Retried, InvoiceState::Updated, InvoiceState::Flagged]
+            .into_iter()
+            .find(|state| state.as_str() == name)
+    }
+}
+
+impl ToSql for InvoiceState {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for InvoiceState {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        InvoiceState::parse(name).ok_or_else(|| FromSqlError::Other(format!("unknown invoice state {}", name).into()))
+    }
+}
+
+impl fmt::Display for InvoiceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The stored state of one invoice.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceRecord {
+    pub invoice_number: String,
+    pub state: InvoiceState,
+    pub voucher_id: Option<String>,
+    pub file_path: Option<String>,
+    /// sha256 of the csv row at upload time
+    pub row_hash: Option<String>,
+    /// sha256 of the pdf at upload time
+    pub file_hash: Option<String>,
+    pub error_category: Option<String>,
+    pub error_message: Option<String>,
+    pub attempts: u32,
+    pub first_seen_at: String,
+    pub updated_at: String,
+    pub uploaded_at: Option<String>,
+    /// why the invoice needs a manual correction in lexoffice, see `record_flagged`
+    pub correction: Option<String>,
+}
+
+/// One lifecycle step of an invoice.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceEvent {
+    pub state: InvoiceState,
+    pub message: Option<String>,
+    pub at: String,
+}
+
+/// Upload state of the invoices of one profile, kept in the sqlite database shared by all profiles.
+pub struct StateStore {
+    conn: Connection,
+    profile: String,
+}
+
+impl StateStore {
+    pub fn open<P: AsRef<Path>>(path: P, profile: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(Error::config(format!("The state database has version {} but this program only supports up to \
+                version {}, please update", version, SCHEMA_VERSION)));
+        }
+        // version 0 is a new file that gets the current schema right away
+        if version == 1 {
+            conn.execute_batch("ALTER TABLE invoices ADD COLUMN correction TEXT;")?;
+        }
+        conn.execute_batch(SCHEMA)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(Self { conn, profile: profile.to_string() })
+    }
+
+    /// Opens the database like `open`, a profile without invoices starts with the ones of its done invoices csv.
+    pub fn open_with_ledger<P: AsRef<Path>>(path: P, profile: &str, ledger: &str) -> Result<Self> {
+        let mut state = Self::open(&path, profile)?;
+        if state.is_empty()? && Path::new(ledger).exists() {
+            info!("Importing {} into {}", ledger, path.as_ref().display());
+            state.import_ledger(ledger)?;
+        }
+        Ok(state)
+    }
+
+    /// Number of invoices known for the profile.
+    pub fn len(&self) -> Result<usize> {
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM invoices WHERE profile = ?1",
+                                             params![self.profile], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Records the invoices of the csv that are not known yet.
+    pub fn mark_seen<'a>(&mut self, invoice_numbers: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let now = now();
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR IGNORE INTO invoices (profile, invoice_number, state, first_seen_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4)")?;
+            let mut event = tx.prepare_cached(
+                "INSERT INTO events (profile, invoice_number, state, at) VALUES (?1, ?2, ?3, ?4)")?;
+            for invoice_number in invoice_numbers {
+                if insert.execute(params![self.profile, invoice_number, InvoiceState::Seen, now])? > 0 {
+                    event.execute(params![self.profile, invoice_number, InvoiceState::Seen, now])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get(&self, invoice_number: &str) -> Result<Option<InvoiceRecord>> {
+        self.conn.query_row("SELECT * FROM invoices WHERE profile = ?1 AND invoice_number = ?2",
+                            params![self.profile, invoice_number], record_from_row)
+            .optional()
+            .map_err(Error::from)
+    }
+
+    pub fn is_uploaded(&self, invoice_number: &str) -> Result<bool> {
+        let state: Option<InvoiceState> = self.conn.query_row(
+            "SELECT state FROM invoices WHERE profile = ?1 AND invoice_number = ?2",
+            params![self.profile, invoice_number], |row| row.get(0)).optional()?;
+        Ok(state == Some(InvoiceState::FileUploaded))
+    }
+
+    /// All invoices of the profile ordered by invoice number.
+    pub fn records(&self) -> Result<Vec<InvoiceRecord>> {
+        let mut statement = self.conn.prepare("SELECT * FROM invoices WHERE profile = ?1 ORDER BY invoice_number")?;
+        let records = statement.query_map(params![self.profile], record_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// The lifecycle of one invoice, oldest first.
+    pub fn events(&self, invoice_number: &str) -> Result<Vec<InvoiceEvent>> {
+        let mut statement = self.conn.prepare(
+            "SELECT state, message, at FROM events WHERE profile = ?1 AND invoice_number = ?2 ORDER BY id")?;
+        let events = statement.query_map(params![self.profile, invoice_number], |row| {
+            Ok(InvoiceEvent {
+                state: row.get(0)?,
+                message: row.get(1)?,
+                at: row.get(2)?,
+            })
+        })?.collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    pub fn record_validated(&self, invoice_number: &str, file_path: &str) -> Result<()> {
+        self.conn.execute("UPDATE invoices SET state = ?3, file_path = ?4, updated_at = ?5
+                           WHERE profile = ?1 AND invoice_number = ?2",
+                          params![self.profile, invoice_number, InvoiceState::Validated, file_path, now()])?;
+        self.event(invoice_number, InvoiceState::Validated, None)
+    }
+
+    pub fn record_voucher_created(&self, invoice_number: &str, voucher_id: &str) -> Result<()> {
+        self.conn.execute("UPDATE invoices SET state = ?3, voucher_id = ?4, updated_at = ?5
+                           WHERE profile = ?1 AND invoice_number = ?2",
+                          params![self.profile, invoice_number, InvoiceState::VoucherCreated, voucher_id, now()])?;
+        self.event(invoice_number, InvoiceState::VoucherCreated, Some(voucher_id))
+    }
+
+    /// Stores the voucher id of an uploaded invoice that had none, e.g. one imported from a done invoices csv.
+    pub fn record_voucher_id(&self, invoice_number: &str, voucher_id: &str) -> Result<()> {
+        self.conn.execute("UPDATE invoices SET voucher_id = ?3, updated_at = ?4 WHERE profile = ?1 AND invoice_number = ?2",
+                          params![self.profile, invoice_number, voucher_id, now()])?;
+        Ok(())
+    }
+
+    pub fn record_uploaded(&self, invoice_number: &str, row_hash: &str, file_hash: &str) -> Result<()> {
+        let now = now();
+        self.conn.execute("UPDATE invoices SET state = ?3, row_hash = ?4, file_hash = ?5, error_category = NULL,
+                           error_message = NULL, updated_at = ?6, uploaded_at = ?6
+                           WHERE profile = ?1 AND invoice_number = ?2",
+                          params![self.profile, invoice_number, InvoiceState::FileUploaded, row_hash, file_hash, now])?;
+        self.event(invoice_number, InvoiceState::FileUploaded, None)
+    }
+
+    /// Marks the invoice as failed. A voucher that was already created is kept so a retry only attaches the pdf.
+    pub fn record_failed(&self, invoice_number: &str, error: &Error) -> Result<()> {
+        let message = error.to_string();
+        self.conn.execute("UPDATE invoices SET state = ?3, error_category = ?4, error_message = ?5,
+                           attempts = attempts + 1, updated_at = ?6
+                           WHERE profile = ?1 AND invoice_number = ?2",
+                          params![self.profile, invoice_number, InvoiceState::Failed,
+                              error.category().to_string(), message, now()])?;
+        self.event(invoice_number, InvoiceState::Failed, Some(&message))
+    }
+
+    /// The voucher was brought in line with the changed csv row, the new hashes are the baseline from now on.
+    pub fn record_updated(&self, invoice_number: &str, row_hash: &str, file_hash: Option<&str>) -> Result<()> {
+        self.conn.execute("UPDATE invoices SET row_hash = ?3, file_hash = COALESCE(?4, file_hash), correction = NULL,
+                           updated_at = ?5 WHERE profile = ?1 AND invoice_number = ?2",
+                          params![self.profile, invoice_number, row_hash, file_hash, now()])?;
+        self.event(invoice_number, InvoiceState::Updated, None)
+    }
+
+    /// Notes that the invoice has to be corrected by hand in lexoffice.
+    pub fn record_flagged(&self, invoice_number: &str, reason: &str) -> Result<()> {
+        self.conn.execute("UPDATE invoices SET correction = ?3, updated_at = ?4 WHERE profile = ?1 AND invoice_number = ?2",
+                          params![self.profile, invoice_number, reason, now()])?;
+        self.event(invoice_number, InvoiceState::Flagged, Some(reason))
+    }
+
+    pub fn record_retried(&self, invoice_number: &str) -> Result<()> {
+        self.event(invoice_number, InvoiceState::Retried, None)
+    }
+
+    fn event(&self, invoice_number: &str, state: InvoiceState, message: Option<&str>) -> Result<()> {
+        self.conn.execute("INSERT INTO events (profile, invoice_number, state, message, at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                          params![self.profile, invoice_number, state, message, now()])?;
+        Ok(())
+    }
+
+    /// Imports a done invoices csv, the invoices count as uploaded. Returns the number of new invoices.
+    pub fn import_ledger(&mut self, path: &str) -> Result<usize> {
+        let done_invoices = invoice::try_read_done_invoices_csv(path.to_string())?;
+        let now = now();
+        let message = format!("imported from {}", path);
+        let tx = self.conn.transaction()?;
+        let mut imported = 0;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR IGNORE INTO invoices (profile, invoice_number, state, first_seen_at, updated_at, uploaded_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4, ?4)")?;
+            let mut event = tx.prepare_cached(
+                "INSERT INTO events (profile, invoice_number, state, message, at) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for done in &done_invoices {
+                let state = InvoiceState::FileUploaded;
+                if insert.execute(params![self.profile, done.invoice_number(), state, now])? > 0 {
+                    event.execute(params![self.profile, done.invoice_number(), state, message, now])?;
+                    imported += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        info!("Imported {} of {} invoices from {}", imported, done_invoices.len(), path);
+        Ok(imported)
+    }
+
+    /// Writes the uploaded invoices as done invoices csv, with voucher id and upload time as extra columns.
+    pub fn export_ledger(&self, path: &str) -> Result<usize> {
+        #[derive(Serialize)]
+        struct ExportRow<'a> {
+            #[serde(rename = "Rechnungsnummer")]
+            invoice_number: &'a str,
+            voucher_id: Option<&'a str>,
+            uploaded_at: Option<&'a str>,
+        }
+
+        let records = self.records()?;
+        let mut wtr = csv::Writer::from_path(path).map_err(|e| Error::csv(path, e))?;
+        let mut exported = 0;
+        for record in records.iter().filter(|record| record.state == InvoiceState::FileUploaded) {
+            wtr.serialize(ExportRow {
+                invoice_number: &record.invoice_number,
+                voucher_id: record.voucher_id.as_deref(),
+                uploaded_at: record.uploaded_at.as_deref(),
+            }).map_err(|e| Error::csv(path, e))?;
+            exported += 1;
+        }
+        wtr.flush()?;
+        Ok(exported)
+    }
+}
+
+/// sha256 of a file as hex string.
+pub fn file_hash(path: &str) -> Result<String> {
+    Ok(format!("{:x}", Sha256::digest(std::fs::read(path)?)))
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339()
+}
+
+fn record_from_row(row: &Row<'_>) -> rusqlite::Result<InvoiceRecord> {
+    Ok(InvoiceRecord {
+        invoice_number: row.get("invoice_number")?,
+        state: row.get("state")?,
+        voucher_id: row.get("voucher_id")?,
+        file_path: row.get("file_path")?,
+        row_hash: row.get("row_hash")?,
+        file_hash: row.get("file_hash")?,
+        error_category: row.get("error_category")?,
+        error_message: row.get("error_message")?,
+        attempts: row.get("attempts")?,
+        first_seen_at: row.get("first_seen_at")?,
+        updated_at: row.get("updated_at")?,
+        uploaded_at: row.get("uploaded_at")?,
+        correction: row.get("correction")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> StateStore {
+        StateStore::open(":memory:", "shop").expect("in-memory database")
+    }
+
+    fn states(store: &StateStore, invoice_number: &str) -> Vec<InvoiceState> {
+        store.events(invoice_number).expect("events").into_iter().map(|event| event.state).collect()
+    }
+
+    #[test]
+    fn invoice_passes_through_its_lifecycle() {
+        let mut store = store();
+        store.mark_seen(["AB-1", "AB-2"]).expect("mark seen");
+        store.mark_seen(["AB-1"]).expect("seen twice");
+        assert_eq!(store.len().expect("count"), 2);
+        assert!(!store.is_uploaded("AB-1").expect("state"));
+
+        store.record_validated("AB-1", "/invoices/AB-1.pdf").expect("validated");
+        store.record_voucher_created("AB-1", "voucher-1").expect("voucher created");
+        assert_eq!(store.get("AB-1").expect("record").map(|record| record.state), Some(InvoiceState::VoucherCreated));
+        store.record_uploaded("AB-1", "row", "file").expect("uploaded");
+
+        let record = store.get("AB-1").expect("record").expect("known invoice");
+        assert_eq!(record.state, InvoiceState::FileUploaded);
+        assert_eq!(record.voucher_id.as_deref(), Some("voucher-1"));
+        assert_eq!(record.file_path.as_deref(), Some("/invoices/AB-1.pdf"));
+        assert_eq!((record.row_hash.as_deref(), record.file_hash.as_deref()), (Some("row"), Some("file")));
+        assert!(record.uploaded_at.is_some());
+        assert!(store.is_uploaded("AB-1").expect("state"));
+        assert_eq!(states(&store, "AB-1"), vec![InvoiceState::Seen, InvoiceState::Validated,
+                                                InvoiceState::VoucherCreated, InvoiceState::FileUploaded]);
+        assert_eq!(store.get("AB-2").expect("record").map(|record| record.state), Some(InvoiceState::Seen));
+        assert!(store.get("AB-3").expect("lookup").is_none());
+    }
+
+    #[test]
+    fn failed_invoice_keeps_its_voucher_for_the_retry() {
+        let mut store = store();
+        store.mark_seen(["AB-1"]).expect("mark seen");
+        store.record_voucher_created("AB-1", "voucher-1").expect("voucher created");
+        store.record_failed("AB-1", &Error::RateLimit { retry_after: Some(5) }).expect("failed");
+
+        let record = store.get("AB-1").expect("record").expect("known invoice");
+        assert_eq!(record.state, InvoiceState::Failed);
+        assert_eq!(record.voucher_id.as_deref(), Some("voucher-1"));
+        assert_eq!(record.error_category.as_deref(), Some("rate-limit"));
+        assert_eq!(record.attempts, 1);
+
+        store.record_retried("AB-1").expect("retried");
+        store.record_failed("AB-1", &Error::MissingFile("/invoices/AB-1.pdf".to_string())).expect("failed again");
+        assert_eq!(store.get("AB-1").expect("record").expect("known invoice").attempts, 2);
+
+        store.record_retried("AB-1").expect("retried");
+        store.record_uploaded("AB-1", "row", "file").expect("uploaded");
+        let record = store.get("AB-1").expect("record").expect("known invoice");
+        assert_eq!(record.state, InvoiceState::FileUploaded);
+        assert_eq!(record.voucher_id.as_deref(), Some("voucher-1"));
+        assert_eq!((record.error_category, record.error_message), (None, None));
+        assert_eq!(states(&store, "AB-1"), vec![InvoiceState::Seen, InvoiceState::VoucherCreated, InvoiceState::Failed,
+                                                InvoiceState::Retried, InvoiceState::Failed, InvoiceState::Retried,
+                                                InvoiceState::FileUploaded]);
+    }
+
+    #[test]
+    fn ledger_import_is_idempotent_and_export_reads_back() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let ledger = dir.path().join("done_invoices.csv");
+        let ledger = ledger.to_str().expect("utf-8 path");
+        std::fs::write(ledger, "Rechnungsnummer\nAB-1\nAB-2\n").expect("write ledger");
+
+        let mut store = store();
+        store.mark_seen(["AB-2", "AB-3"]).expect("mark seen");
+        assert_eq!(store.import_ledger(ledger).expect("import"), 1, "AB-2 is already known");
+        assert_eq!(store.import_ledger(ledger).expect("second import"), 0);
+        assert!(store.is_uploaded("AB-1").expect("state"));
+        assert!(!store.is_uploaded("AB-2").expect("state"));
+
+        store.record_voucher_created("AB-3", "voucher-3").expect("voucher created");
+        store.record_uploaded("AB-3", "row", "file").expect("uploaded");
+        let export = dir.path().join("export.csv");
+        let export = export.to_str().expect("utf-8 path");
+        assert_eq!(store.export_ledger(export).expect("export"), 2);
+
+        let mut other = StateStore::open(":memory:", "shop").expect("in-memory database");
+        assert_eq!(other.import_ledger(export).expect("import export"), 2);
+        let uploaded: Vec<String> = other.records().expect("records").into_iter()
+            .filter(|record| record.state == InvoiceState::FileUploaded)
+            .map(|record| record.invoice_number)
+            .collect();
+        assert_eq!(uploaded, vec!["AB-1", "AB-3"]);
+    }
+
+    #[test]
+    fn ledger_is_only_imported_into_an_empty_profile() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("state.db");
+        let ledger = dir.path().join("done_invoices.csv");
+        let ledger = ledger.to_str().expect("utf-8 path");
+        std::fs::write(ledger, "Rechnungsnummer\nAB-1\n").expect("write ledger");
+
+        let store = StateStore::open_with_ledger(&path, "shop", ledger).expect("open");
+        assert!(store.is_uploaded("AB-1").expect("state"));
+        drop(store);
+
+        std::fs::write(ledger, "Rechnungsnummer\nAB-1\nAB-2\n").expect("write ledger");
+        let store = StateStore::open_with_ledger(&path, "shop", ledger).expect("reopen");
+        assert_eq!(store.len().expect("count"), 1, "the ledger is not read again once the profile has invoices");
+        let missing = dir.path().join("missing.csv");
+        let other = StateStore::open_with_ledger(&path, "trading", missing.to_str().expect("utf-8 path")).expect("open");
+        assert!(other.is_empty().expect("count"));
+    }
+
+    #[test]
+    fn profiles_share_the_database_but_not_their_invoices() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("state.db");
+        let mut shop = StateStore::open(&path, "shop").expect("open");
+        shop.mark_seen(["AB-1"]).expect("mark seen");
+        let trading = StateStore::open(&path, "trading").expect("open");
+        assert!(trading.is_empty().expect("count"));
+        assert!(!shop.is_empty().expect("count"));
+    }
+
+    #[test]
+    fn version_one_databases_get_the_correction_column() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("state.db");
+        {
+            let conn = Connection::open(&path).expect("create database");
+            let version_one = SCHEMA.replace("    correction TEXT,\n", "");
+            conn.execute_batch(&version_one).expect("version 1 schema");
+            conn.execute("INSERT INTO invoices (profile, invoice_number, state, first_seen_at, updated_at)
+                          VALUES ('shop', 'AB-1', 'file-uploaded', 'then', 'then')", []).expect("insert");
+            conn.pragma_update(None, "user_version", 1).expect("set version");
+        }
+
+        let store = StateStore::open(&path, "shop").expect("migrated database");
+        store.record_flagged("AB-1", "amount changed").expect("flag");
+        let record = store.get("AB-1").expect("record").expect("known invoice");
+        assert_eq!(record.state, InvoiceState::FileUploaded);
+        assert_eq!(record.correction.as_deref(), Some("amount changed"));
+        let version: i64 = store.conn.pragma_query_value(None, "user_version", |row| row.get(0)).expect("version");
+        assert_eq!(version, SCHEMA_VERSION);
+
+        drop(store);
+        StateStore::open(&path, "shop").expect("reopened database");
+        let conn = Connection::open(&path).expect("open database");
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).expect("set version");
+        drop(conn);
+        assert!(matches!(StateStore::open(&path, "shop"), Err(Error::Config(_))));
+    }
+}
@@ -0,0 +1,337 @@
+use std::fmt;
+use chrono::NaiveDate;
+use log::warn;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use crate::countries;
+use crate::error::{Error, Result};
+use crate::invoice::invoice::InvoiceCSV;
+use crate::settings::{CategoryConfig, Config};
+use crate::vat_rates;
+
+/// Country of the seller, used unless `home_country` is configured.
+pub const DEFAULT_HOME_COUNTRY: &str = "DE";
+
+/// lexoffice revenue categories of the tax cases, see the categories of the lexoffice voucher api.
+pub const DOMESTIC_CATEGORY_ID: &str = "8f8664a1-fd86-11e1-a21f-0800200c9a66";
+pub const INTRA_COMMUNITY_CATEGORY_ID: &str = "9075a4e3-66de-4795-a016-3889feca0d20";
+pub const OSS_CATEGORY_ID: &str = "7c112b66-0565-479c-bc18-5845e080880a";
+pub const EXPORT_CATEGORY_ID: &str = "93d24c20-ea84-424e-a731-5e1b78d1e6a9";
+
+/// How a sale is taxed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaxCase {
+    /// customer in the home country, regular VAT
+    Domestic,
+    /// business in another EU country with VAT id, reverse charge at 0%
+    IntraCommunity,
+    /// consumer (or business without VAT id) in another EU country, VAT of the destination country
+    Oss,
+    /// customer outside the EU, 0%
+    Export,
+}
+
+impl TaxCase {
+    /// Rates that fit the case unless `rates` is configured, `None` means any rate above 0%.
+    /// Domestic and OSS sales take the rates of the country on the delivery date from `vat_rates`.
+    fn default_rates(self, country: &str, delivery_date: NaiveDate) -> Option<Vec<Decimal>> {
+        match self {
+            TaxCase::Domestic | TaxCase::Oss => vat_rates::rates(country, delivery_date).map(|rates| rates.all()),
+            TaxCase::IntraCommunity | TaxCase::Export => Some(vec![Decimal::ZERO]),
+        }
+    }
+
+    pub fn default_category_id(self) -> &'static str {
+        match self {
+            TaxCase::Domestic => DOMESTIC_CATEGORY_ID,
+            TaxCase::IntraCommunity => INTRA_COMMUNITY_CATEGORY_ID,
+            TaxCase::Oss => OSS_CATEGORY_ID,
+            TaxCase::Export => EXPORT_CATEGORY_ID,
+        }
+    }
+}
+
+impl fmt::Display for TaxCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TaxCase::Domestic => "domestic",
+            TaxCase::IntraCommunity => "intra-community",
+            TaxCase::Oss => "oss",
+            TaxCase::Export => "export",
+        })
+    }
+}
+
+/// Overrides of the tax type, category and accepted rates of one tax case.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaxCaseConfig {
+    pub case: TaxCase,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rates: Option<Vec<Decimal>>,
+}
+
+/// How the voucher of an invoice is booked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaxTreatment {
+    pub case: TaxCase,
+    pub country: String,
+    /// `net` or `gross`
+    pub tax_type: String,
+    pub category_id: String,
+}
+
+/// Determines the tax case from the billing country, the transaction type and the VAT id column.
+/// Businesses in other EU countries without a VAT id are treated like consumers. Addresses without a
+/// recognizable country, e.g. `Shop GmbH, Hauptstr. 1, 10115 Berlin`, are taken to be in the home country
+/// unless they name a state or province like `Los Angeles, CA`, which is an error.
+pub fn detect<'a>(invoice: &InvoiceCSV, config: &'a Config) -> Result<(TaxCase, &'a str)> {
+    let address = invoice.parsed_billing_address();
+    let country = match (address.country_code, address.region) {
+        (Some(country), _) => country,
+        (None, Some(region)) => return Err(Error::Tax {
+            invoice: invoice.invoice_number().to_string(),
+            message: format!("the billing address {} names the region {} but no country", invoice.billing_address(), region),
+        }),
+        (None, None) => {
+            warn!("No country in the billing address {} of invoice {}, assuming {}",
+                  invoice.billing_address(), invoice.invoice_number(), config.home_country());
+            config.home_country()
+        }
+    };
+    let case = if country == config.home_country() {
+        TaxCase::Domestic
+    } else if !countries::is_eu(country) {
+        TaxCase::Export
+    } else if invoice.transaction_type() == "b2b" && invoice.vat_id().is_some() {
+        TaxCase::IntraCommunity
+    } else {
+        TaxCase::Oss
+    };
+    Ok((case, country))
+}
+
+/// Detects the tax case, checks the rate of the invoice against it and resolves tax type and category.
+/// The category comes from `tax_cases`, then from `categories` by transaction type and case, then from the case.
+pub fn treatment(invoice: &InvoiceCSV, config: &Config) -> Result<TaxTreatment> {
+    let (case, country) = detect(invoice, config)?;
+    let overrides = config.tax_case(case);
+
+    let rate = invoice.vat_rate();
+    let rates = overrides.and_then(|overrides| overrides.rates.clone())
+        .or_else(|| case.default_rates(country, invoice.delivery_date()));
+    let rate_fits = match &rates {
+        Some(rates) => rates.contains(&rate),
+        None => rate > Decimal::ZERO,
+    };
+    if !rate_fits {
+        let expected = match rates {
+            Some(rates) => rates.iter().map(|rate| format!("{}%", rate.normalize())).collect::<Vec<_>>().join(" or "),
+            None => "more than 0%".to_string(),
+        };
+        return Err(Error::Tax {
+            invoice: invoice.invoice_number().to_string(),
+            message: format!("VAT rate {}% does not fit the tax case {} ({} on {}), expected {}",
+                             rate.normalize(), case, country, invoice.delivery_date(), expected),
+        });
+    }
+
+    let tax_type = overrides.and_then(|overrides| overrides.tax_type.clone())
+        .unwrap_or_else(|| if invoice.transaction_type() == "b2b" { "net".to_string() } else { "gross".to_string() });
+    let category_id = overrides.and_then(|overrides| overrides.category_id.clone())
+        .or_else(|| config.transaction_category(invoice.transaction_type(), case).map(str::to_string))
+        .unwrap_or_else(|| case.default_category_id().to_string());
+    Ok(TaxTreatment { case, country: country.to_string(), tax_type, category_id })
+}
+
+/// The `categories` entries for the transaction type of the invoice that are not used for its tax case because
+/// they name another case or none, e.g. `b2b` booked as Innergemeinschaftliche Lieferung for a domestic sale.
+pub fn mismatched_categories<'a>(invoice: &InvoiceCSV, treatment: &TaxTreatment, config: &'a Config) -> Vec<&'a CategoryConfig> {
+    config.categories.iter().flatten()
+        .filter(|category| category.transaction_type == invoice.transaction_type())
+        .filter(|category| category.case != Some(treatment.case) && category.category_id != treatment.category_id)
+        .collect()
+}
+
+/// Prints every invoice whose tax case cannot be determined, whose rate does not fit or whose configured
+/// category does not match its tax case, returns their number.
+pub fn check_invoices(config: &Config, invoices: &[InvoiceCSV]) -> usize {
+    let mut problems = 0;
+    for invoice in invoices {
+        match treatment(invoice, config) {
+            Ok(treatment) => {
+                let mismatched = mismatched_categories(invoice, &treatment, config);
+                for category in &mismatched {
+                    let configured_case = category.case.map(|case| case.to_string()).unwrap_or_else(|| "no tax case".to_string());
+                    println!("  Invoice {}: category {} of transaction type {} is configured for {} and not used \
+                              for the tax case {}, booked to {}", invoice.invoice_number(), category.category_id,
+                             category.transaction_type, configured_case, treatment.case, treatment.category_id);
+                }
+                if !mismatched.is_empty() {
+                    problems += 1;
+                }
+            }
+            Err(e) => {
+                println!("  {}", e);
+                problems += 1;
+            }
+        }
+    }
+    println!("{} of {} invoices have tax problems (VAT rate table {})", problems, invoices.len(), vat_rates::VAT_RATES_VERSION);
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::CURRENT_VERSION;
+
+    const CONTACT_ID: &str = "11111111-2222-3333-4444-555555555555";
+
+    /// One csv row delivered on 2024-01-10, amounts written like in the exports, e.g. `119,00`.
+    fn invoice(address: &str, transaction_type: &str, vat_id: &str, net: &str, vat: &str, gross: &str) -> InvoiceCSV {
+        let csv = format!("Rechnungsnummer,Interne Referenz,Rechnungsdatum,Lieferdatum,Netto,USt. Rate (%),Endbetrag,\
+                           Währung,Transaktionstyp,Rechnungsadresse,USt-IdNr.\n\
+                           AB-1,,15.01.2024,10.01.2024,\"{}\",\"{}\",\"{}\",EUR,{},\"{}\",{}\n",
+                          net, vat, gross, transaction_type, address, vat_id);
+        csv::Reader::from_reader(csv.as_bytes()).deserialize().next().expect("one row").expect("valid row")
+    }
+
+    /// A config mapping every address used here to the same contact, so building a voucher never prompts.
+    fn config(extra: &str) -> Config {
+        let addresses = [GERMANY, NO_COUNTRY, NETHERLANDS, FRANCE, USA];
+        let customers: String = addresses.iter()
+            .map(|address| format!("  - customer_id: {}\n    customer_adress: \"{}\"\n", CONTACT_ID, address))
+            .collect();
+        serde_yaml::from_str(&format!("version: {}\nprefixes: []\ncustomers:\n{}{}", CURRENT_VERSION, customers, extra)).expect("valid config")
+    }
+
+    const GERMANY: &str = "Shop GmbH, Hauptstr. 1, 10115 Berlin, Deutschland";
+    const NO_COUNTRY: &str = "Shop GmbH, Hauptstr. 1, 10115 Berlin";
+    const NETHERLANDS: &str = "Firma BV, Keizersgracht 1, 1015 Amsterdam, Netherlands";
+    const FRANCE: &str = "Jean Dupont, 1 Rue de Rivoli, 75001, Paris, France";
+    const USA: &str = "GOAT, 3433 W Exposition Place, 90018, Los Angeles (CA), USA";
+
+    #[test]
+    fn detects_the_tax_case_from_country_transaction_type_and_vat_id() {
+        let config = config("");
+        let cases = [
+            (GERMANY, "b2c", "", TaxCase::Domestic, "DE"),
+            (NETHERLANDS, "b2b", "NL123456789B01", TaxCase::IntraCommunity, "NL"),
+            (NETHERLANDS, "b2b", "", TaxCase::Oss, "NL"),
+            (FRANCE, "b2c", "", TaxCase::Oss, "FR"),
+            (USA, "b2b", "", TaxCase::Export, "US"),
+        ];
+        for (address, transaction_type, vat_id, case, country) in cases {
+            let invoice = invoice(address, transaction_type, vat_id, "100,00", "0,00", "100,00");
+            assert_eq!(detect(&invoice, &config).expect("country is known"), (case, country), "{}", address);
+        }
+    }
+
+    #[test]
+    fn addresses_without_country_are_in_the_home_country() {
+        let invoice = invoice(NO_COUNTRY, "b2c", "", "100,00", "19,00", "119,00");
+        assert_eq!(detect(&invoice, &config("")).expect("home country"), (TaxCase::Domestic, "DE"));
+        assert_eq!(detect(&invoice, &config("home_country: AT\n")).expect("home country"), (TaxCase::Domestic, "AT"));
+    }
+
+    #[test]
+    fn addresses_with_region_but_without_country_are_rejected() {
+        let invoice = invoice("GOAT, 3433 W Exposition Place, Los Angeles, CA", "b2b", "", "100,00", "0,00", "100,00");
+        assert!(matches!(detect(&invoice, &config("")), Err(Error::Tax { .. })));
+    }
+
+    #[test]
+    fn treatment_checks_the_rate_against_the_case() {
+        let config = config("");
+        let oss = treatment(&invoice(FRANCE, "b2c", "", "100,00", "20,00", "120,00"), &config).expect("20% is the French rate");
+        assert_eq!(oss.case, TaxCase::Oss);
+        assert_eq!(oss.tax_type, "gross");
+        assert_eq!(oss.category_id, OSS_CATEGORY_ID);
+
+        let wrong_rate = treatment(&invoice(FRANCE, "b2c", "", "100,00", "19,00", "119,00"), &config);
+        assert!(matches!(wrong_rate, Err(Error::Tax { .. })));
+        let taxed_export = treatment(&invoice(USA, "b2b", "", "100,00", "19,00", "119,00"), &config);
+        assert!(matches!(taxed_export, Err(Error::Tax { .. })));
+
+        let intra = treatment(&invoice(NETHERLANDS, "b2b", "NL123456789B01", "100,00", "0,00", "100,00"), &config)
+            .expect("0% fits intra-community sales");
+        assert_eq!(intra.tax_type, "net");
+        assert_eq!(intra.category_id, INTRA_COMMUNITY_CATEGORY_ID);
+    }
+
+    #[test]
+    fn configured_categories_override_the_case_default() {
+        let invoice = invoice(GERMANY, "b2b", "", "100,00", "19,00", "119,00");
+        let by_type_and_case = config("categories:\n  - transaction_type: b2b\n    case: domestic\n    category_id: by-type\n");
+        assert_eq!(treatment(&invoice, &by_type_and_case).expect("valid").category_id, "by-type");
+
+        let by_case = config("categories:\n  - transaction_type: b2b\n    case: domestic\n    category_id: by-type\n\
+                              tax_cases:\n  - case: domestic\n    category_id: by-case\n    tax_type: gross\n");
+        let treatment = treatment(&invoice, &by_case).expect("valid");
+        assert_eq!(treatment.category_id, "by-case");
+        assert_eq!(treatment.tax_type, "gross");
+    }
+
+    #[test]
+    fn categories_of_other_cases_are_not_used_and_reported() {
+        let current = config(&format!("categories:\n  - transaction_type: b2b\n    category_id: {}\n\
+                                        \x20 - transaction_type: b2b\n    case: intra-community\n    category_id: by-type\n",
+                                       INTRA_COMMUNITY_CATEGORY_ID));
+        let invoices = [
+            invoice(GERMANY, "b2b", "", "100,00", "19,00", "119,00"),
+            invoice(NETHERLANDS, "b2b", "NL123456789B01", "100,00", "0,00", "100,00"),
+        ];
+        let [domestic, intra] = &invoices;
+        let mismatched = |invoice, config| {
+            let booked = treatment(invoice, config).expect("valid");
+            let categories = mismatched_categories(invoice, &booked, config).iter()
+                .map(|category| category.category_id.clone())
+                .collect::<Vec<_>>();
+            (booked.category_id, categories)
+        };
+
+        assert_eq!(mismatched(domestic, &current),
+                   (DOMESTIC_CATEGORY_ID.to_string(), vec![INTRA_COMMUNITY_CATEGORY_ID.to_string(), "by-type".to_string()]));
+        assert_eq!(mismatched(intra, &current), ("by-type".to_string(), vec![INTRA_COMMUNITY_CATEGORY_ID.to_string()]),
+                   "the entry without case is not used");
+        assert_eq!(check_invoices(&current, &invoices), 2);
+
+        let legacy = config(&format!("categories:\n  - transaction_type: b2b\n    category_id: {}\n", INTRA_COMMUNITY_CATEGORY_ID));
+        assert_eq!(mismatched(intra, &legacy), (INTRA_COMMUNITY_CATEGORY_ID.to_string(), vec![]),
+                   "booked to the same category anyway");
+        assert_eq!(check_invoices(&legacy, &invoices), 1);
+    }
+
+    fn amount(value: &str) -> Decimal {
+        value.parse().expect("valid decimal")
+    }
+
+    #[test]
+    fn voucher_totals_per_tax_case() {
+        // address, transaction type, vat id, net, rate, gross, expected tax type, item amount, tax amount
+        let cases = [
+            (GERMANY, "b2c", "", "100,00", "19,00", "119,00", "gross", amount("119.00"), amount("19.00")),
+            (NO_COUNTRY, "b2b", "", "100,00", "7,00", "107,00", "net", amount("100.00"), amount("7.00")),
+            (NETHERLANDS, "b2b", "NL123456789B01", "100,00", "0,00", "100,00", "net", amount("100.00"), amount("0.00")),
+            (FRANCE, "b2c", "", "100,00", "20,00", "120,00", "gross", amount("120.00"), amount("20.00")),
+            (USA, "b2c", "", "50,00", "0,00", "50,00", "gross", amount("50.00"), amount("0.00")),
+        ];
+        let mut config = config("");
+        for (address, transaction_type, vat_id, net, rate, gross, tax_type, amount, tax_amount) in cases {
+            let voucher = invoice(address, transaction_type, vat_id, net, rate, gross).build_voucher(&mut config)
+                .expect("voucher can be built");
+            assert_eq!(voucher.tax_type, tax_type, "{}", address);
+            let item_gross = if tax_type == "net" { voucher.voucher_items[0].amount + tax_amount } else { voucher.voucher_items[0].amount };
+            assert_eq!(voucher.total_gross_amount, item_gross, "{}", address);
+            assert_eq!(voucher.total_tax_amount, tax_amount, "{}", address);
+            assert_eq!(voucher.voucher_items[0].amount, amount, "{}", address);
+            assert_eq!(voucher.voucher_items[0].tax_amount, tax_amount, "{}", address);
+            assert_eq!(voucher.contact_id, CONTACT_ID);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+use log::{debug, error, info};
+use reqwest::{multipart, Body, Client, StatusCode};
+use tokio::fs::File;
+use tokio_util::codec::{BytesCodec, FramedRead};
+use crate::error::{Error, ErrorCategory, Result};
+use crate::failures;
+use crate::invoice::invoice::{InvoiceCSV, VoucherCreateRequest};
+use crate::lexoffice::{self, Profile, VoucherCreationResponse, BASE_URL};
+use crate::settings::{Config, STATE_PATH};
+use crate::state::{self, InvoiceState, StateStore};
+
+/// lexoffice rejects files above 5 MB
+const MAX_FILE_SIZE: u64 = 5_000_000;
+
+/// Progress notifications emitted by `Uploader::run`.
+#[derive(Debug)]
+pub enum UploadEvent<'a> {
+    /// The state database was read, `total` invoices are going to be uploaded.
+    Started { total: usize },
+    InvoiceStarted { invoice: &'a InvoiceCSV, index: usize, total: usize },
+    InvoiceFinished { invoice: &'a InvoiceCSV, index: usize, total: usize, duration: Duration, error: Option<&'a Error> },
+    Finished { uploaded: usize, failed: usize },
+}
+
+/// Outcome of one `Uploader::run`.
+#[derive(Debug, Default)]
+pub struct UploadSummary {
+    /// invoices that were already uploaded
+    pub skipped: usize,
+    pub uploaded: Vec<UploadedInvoice>,
+    pub failed: Vec<FailedInvoice>,
+}
+
+impl UploadSummary {
+    pub fn is_uploaded(&self, invoice_number: &str) -> bool {
+        self.uploaded.iter().any(|uploaded| uploaded.invoice_number == invoice_number)
+    }
+}
+
+/// Voucher created by `Uploader::upload`.
+#[derive(Debug, Clone)]
+pub struct UploadedVoucher {
+    pub voucher_id: String,
+    pub file_path: String,
+}
+
+#[derive(Debug)]
+pub struct UploadedInvoice {
+    pub invoice_number: String,
+    pub voucher_id: String,
+    pub file_path: String,
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+pub struct FailedInvoice {
+    pub invoice_number: String,
+    /// the pdf that belongs to the invoice, unknown if its prefix could not be resolved
+    pub file_path: Option<String>,
+    pub duration: Duration,
+    pub error: Error,
+}
+
+impl FailedInvoice {
+    pub fn category(&self) -> ErrorCategory {
+        self.error.category()
+    }
+}
+
+type ProgressCallback = Box<dyn FnMut(&UploadEvent<'_>) + Send>;
+
+/// Uploads invoices with the settings of one profile and keeps its state database up to date.
+pub struct Uploader {
+    config: Config,
+    client: Client,
+    progress: Option<ProgressCallback>,
+}
+
+impl Uploader {
+    pub fn new(config: Config) -> Self {
+        Self { config, client: Client::new(), progress: None }
+    }
+
+    /// Registers a callback that is invoked for every `UploadEvent`.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+        where
+            F: FnMut(&UploadEvent<'_>) + Send + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Resolves the api key and checks it against lexoffice, see `lexoffice::check_api_key`.
+    pub async fn prepare(&mut self) -> Result<Profile> {
+        self.config.resolve_api_key()?;
+        if !self.config.validate() {
+            error!("Settings file failed validation, attempting to get new api key from user");
+            if !self.config.invalidate_api_key() {
+                return Err(Error::ApiAuth("No valid api key configured".to_string()));
+            }
+        }
+        lexoffice::check_api_key(&mut self.config).await
+    }
+
+    /// The invoices that are not uploaded yet according to the state database.
+    pub fn pending<'a>(&self, invoices: &[&'a InvoiceCSV]) -> Result<Vec<&'a InvoiceCSV>> {
+        let state = self.open_state()?;
+        not_done(invoices, &state)
+    }
+
+    /// Opens the state database of the profile, a new one starts with the invoices of the done invoices csv.
+    fn open_state(&self) -> Result<StateStore> {
+        StateStore::open_with_ledger(STATE_PATH, self.config.display_name(), &self.config.ledger_path())
+    }
+
+    /// Uploads every invoice that is not uploaded yet and tracks each step in the state database.
+    /// Fails only if the state database cannot be used, upload errors end up in the summary.
+    pub async fn run(&mut self, invoices: &[&InvoiceCSV]) -> Result<UploadSummary> {
+        let mut state = self.open_state()?;
+        state.mark_seen(invoices.iter().map(|invoice| invoice.invoice_number()))?;
+
+        let to_upload = not_done(invoices, &state)?;
+
+        info!("Found {} invoices to upload", to_upload.len());
+        let mut summary = UploadSummary { skipped: invoices.len() - to_upload.len(), ..UploadSummary::default() };
+        let total = to_upload.len();
+        self.emit(&UploadEvent::Started { total });
+
+        for (index, invoice) in to_upload.into_iter().enumerate() {
+            debug!("Uploading invoice {}", invoice.invoice_number());
+            self.emit(&UploadEvent::InvoiceStarted { invoice, index, total });
+            let time_start = Instant::now();
+            let result = self.upload_tracked(invoice, &state).await;
+            let duration = time_start.elapsed();
+
+            match &result {
+                Err(e) => {
+                    error!("Error uploading invoice {}: {}", invoice.invoice_number(), e);
+                    state.record_failed(invoice.invoice_number(), e)?;
+                }
+                Ok(voucher) => {
+                    info!("Uploaded invoice {} in {}ms", invoice.invoice_number(), duration.as_millis());
+                    summary.uploaded.push(UploadedInvoice {
+                        invoice_number: invoice.invoice_number().to_string(),
+                        voucher_id: voucher.voucher_id.clone(),
+                        file_path: voucher.file_path.clone(),
+                        duration,
+                    });
+                }
+            }
+            self.emit(&UploadEvent::InvoiceFinished { invoice, index, total, duration, error: result.as_ref().err() });
+            if let Err(error) = result {
+                summary.failed.push(FailedInvoice {
+                    invoice_number: invoice.invoice_number().to_string(),
+                    file_path: invoice.mapped_file_path(&self.config),
+                    duration,
+                    error,
+                });
+            }
+        }
+
+        let failures_path = self.config.failures_path();
+        let open_failures = failures::update_failures(&failures_path, &summary)?;
+        if !open_failures.is_empty() {
+            info!("{} failed invoices are recorded in {}", open_failures.len(), failures_path);
+        }
+        self.emit(&UploadEvent::Finished { uploaded: summary.uploaded.len(), failed: summary.failed.len() });
+        Ok(summary)
+    }
+
+    fn emit(&mut self, event: &UploadEvent<'_>) {
+        if let Some(callback) = self.progress.as_mut() {
+            callback(event);
+        }
+    }
+
+    /// Creates the voucher for one invoice and attaches its pdf, without touching the state database.
+    pub async fn upload(&mut self, invoice: &InvoiceCSV) -> Result<UploadedVoucher> {
+        let file_path = invoice.file_path(&mut self.config)?;
+        check_file(&file_path)?;
+        let request = invoice.build_voucher(&mut self.config)?;
+        let voucher_id = self.create_voucher(invoice, &request).await?;
+        self.attach_file(&voucher_id, &file_path).await?;
+        Ok(UploadedVoucher { voucher_id, file_path })
+    }
+
+    /// Like `upload` but records every step. A voucher created by an earlier attempt is reused,
+    /// so a failed file upload does not lead to a second voucher.
+    async fn upload_tracked(&mut self, invoice: &InvoiceCSV, state: &StateStore) -> Result<UploadedVoucher> {
+        let invoice_number = invoice.invoice_number();
+        let previous = state.get(invoice_number)?;
+        if previous.as_ref().is_some_and(|record| record.state == InvoiceState::Failed) {
+            state.record_retried(invoice_number)?;
+        }
+
+        let file_path = invoice.file_path(&mut self.config)?;
+        check_file(&file_path)?;
+        let request = invoice.build_voucher(&mut self.config)?;
+        state.record_validated(invoice_number, &file_path)?;
+
+        let voucher_id = match previous.and_then(|record| record.voucher_id) {
+            Some(voucher_id) => {
+                info!("Voucher {} of invoice {} already exists, only attaching the file", voucher_id, invoice_number);
+                voucher_id
+            }
+            None => {
+                let voucher_id = self.create_voucher(invoice, &request).await?;
+                state.record_voucher_created(invoice_number, &voucher_id)?;
+                voucher_id
+            }
+        };
+        self.attach_file(&voucher_id, &file_path).await?;
+        state.record_uploaded(invoice_number, &invoice.content_hash(), &state::file_hash(&file_path)?)?;
+        Ok(UploadedVoucher { voucher_id, file_path })
+    }
+
+    /// Brings the voucher of an uploaded invoice in line with the current csv row, the attached pdf stays as it is.
+    pub async fn update_voucher(&mut self, invoice: &InvoiceCSV, voucher_id: &str) -> Result<()> {
+        let request = invoice.build_voucher(&mut self.config)?;
+        let voucher = lexoffice::get_voucher(&self.client, &self.config, voucher_id).await?;
+        lexoffice::update_voucher(&self.client, &self.config, voucher_id, &request, voucher.version).await?;
+        info!("Updated voucher {} of invoice {}", voucher_id, invoice.invoice_number());
+        Ok(())
+    }
+
+    async fn create_voucher(&mut self, invoice: &InvoiceCSV, upload_req: &VoucherCreateRequest) -> Result<String> {
+        let res = loop {
+            let res: reqwest::Response = self.client.post(format!("{}vouchers", BASE_URL))
+                .bearer_auth(self.config.api_key().expose())
+                .json(upload_req)
+                .send()
+                .await?;
+
+            if res.status() != StatusCode::UNAUTHORIZED {
+                break res;
+            }
+            error!("API key is invalid! Please enter new one in the config file.");
+            let error = lexoffice::error_from_response(res).await;
+            error!("Error: {}", error);
+
+            if !self.config.invalidate_api_key() {
+                return Err(error);
+            }
+        };
+
+        if res.status() != 200 {
+            error!("Error while uploading invoice {}, got status code {}!", invoice.invoice_number(), res.status());
+            let error = lexoffice::error_from_response(res).await;
+            error!("Error: {}", error);
+            return Err(error);
+        }
+
+        let result = res.json::<VoucherCreationResponse>().await?;
+        info!("Successfully created voucher with id {}", result.id);
+        Ok(result.id)
+    }
+
+    async fn attach_file(&self, voucher_id: &str, file_path: &str) -> Result<()> {
+        debug!("Uploading file {} to voucher {}", file_path, voucher_id);
+
+        let url = format!("{}vouchers/{}/files", BASE_URL, voucher_id);
+
+        let file = File::open(file_path).await?;
+
+        // read file body stream
+        let stream = FramedRead::new(file, BytesCodec::new());
+        let file_body = Body::wrap_stream(stream);
+
+        //make form part of file
+        let some_file = multipart::Part::stream(file_body)
+            .file_name("invoice.pdf")
+            .mime_str("application/pdf")?;
+
+        let form = multipart::Form::new()
+            .text("type", "voucher")
+            .part("file", some_file);
+
+        let upload_res = self.client.post(&url)
+            .bearer_auth(self.config.api_key().expose())
+            .multipart(form)
+            .send()
+            .await?;
+
+        if upload_res.status() != 202 {
+            error!("Error during file upload");
+            return Err(lexoffice::error_from_response(upload_res).await);
+        }
+
+        info!("Successfully uploaded file {} to voucher {}", file_path, voucher_id);
+        Ok(())
+    }
+}
+
+/// Checks that the pdf exists, is not empty and is smaller than 5 MB (limitation of lexoffice).
+fn check_file(file_path: &str) -> Result<()> {
+    if !Path::new(file_path).exists() {
+        error!("File {} does not exist!", file_path);
+        return Err(Error::MissingFile(file_path.to_string()));
+    }
+
+    let metadata = std::fs::metadata(file_path)?;
+    if metadata.len() == 0 || metadata.len() > MAX_FILE_SIZE {
+        error!("File {} is empty or too large!", file_path);
+        return Err(Error::FileTooLarge { path: file_path.to_string(), size: metadata.len() });
+    }
+    Ok(())
+}
+
+fn not_done<'a>(invoices: &[&'a InvoiceCSV], state: &StateStore) -> Result<Vec<&'a InvoiceCSV>> {
+    let mut pending = vec![];
+    for invoice in invoices {
+        if !state.is_uploaded(invoice.invoice_number())? {
+            pending.push(*invoice);
+        }
+    }
+    Ok(pending)
+}
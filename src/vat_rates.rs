@@ -0,0 +1,121 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+/// Version of the built-in table, bump it together with the entries whenever a member state changes its rates.
+pub const VAT_RATES_VERSION: &str = "2025-08";
+
+/// VAT rates of an EU member state from `valid_from` until the next entry of the same country.
+/// Rates are in tenths of a percent, `55` is 5.5%.
+struct RateEntry {
+    country: &'static str,
+    valid_from: (i32, u32, u32),
+    standard: u32,
+    /// reduced and super reduced rates
+    reduced: &'static [u32],
+}
+
+/// Standard and reduced rates since the start of the One-Stop-Shop on 2021-07-01, oldest entry first per country.
+const RATES: &[RateEntry] = &[
+    RateEntry { country: "AT", valid_from: (2021, 7, 1), standard: 200, reduced: &[130, 100] },
+    RateEntry { country: "BE", valid_from: (2021, 7, 1), standard: 210, reduced: &[120, 60] },
+    RateEntry { country: "BG", valid_from: (2021, 7, 1), standard: 200, reduced: &[90] },
+    RateEntry { country: "CY", valid_from: (2021, 7, 1), standard: 190, reduced: &[90, 50, 30] },
+    RateEntry { country: "CZ", valid_from: (2021, 7, 1), standard: 210, reduced: &[150, 100] },
+    RateEntry { country: "CZ", valid_from: (2024, 1, 1), standard: 210, reduced: &[120] },
+    RateEntry { country: "DE", valid_from: (2021, 7, 1), standard: 190, reduced: &[70] },
+    RateEntry { country: "DK", valid_from: (2021, 7, 1), standard: 250, reduced: &[] },
+    RateEntry { country: "EE", valid_from: (2021, 7, 1), standard: 200, reduced: &[90] },
+    RateEntry { country: "EE", valid_from: (2024, 1, 1), standard: 220, reduced: &[90, 50] },
+    RateEntry { country: "EE", valid_from: (2025, 7, 1), standard: 240, reduced: &[90, 50] },
+    RateEntry { country: "ES", valid_from: (2021, 7, 1), standard: 210, reduced: &[100, 40] },
+    RateEntry { country: "FI", valid_from: (2021, 7, 1), standard: 240, reduced: &[140, 100] },
+    RateEntry { country: "FI", valid_from: (2024, 9, 1), standard: 255, reduced: &[140, 100] },
+    RateEntry { country: "FI", valid_from: (2025, 1, 1), standard: 255, reduced: &[140, 135, 100] },
+    RateEntry { country: "FR", valid_from: (2021, 7, 1), standard: 200, reduced: &[100, 55, 21] },
+    RateEntry { country: "GR", valid_from: (2021, 7, 1), standard: 240, reduced: &[130, 60] },
+    RateEntry { country: "HR", valid_from: (2021, 7, 1), standard: 250, reduced: &[130, 50] },
+    RateEntry { country: "HU", valid_from: (2021, 7, 1), standard: 270, reduced: &[180, 50] },
+    RateEntry { country: "IE", valid_from: (2021, 7, 1), standard: 230, reduced: &[135, 90, 48] },
+    RateEntry { country: "IT", valid_from: (2021, 7, 1), standard: 220, reduced: &[100, 50, 40] },
+    RateEntry { country: "LT", valid_from: (2021, 7, 1), standard: 210, reduced: &[90, 50] },
+    RateEntry { country: "LU", valid_from: (2021, 7, 1), standard: 170, reduced: &[140, 80, 30] },
+    RateEntry { country: "LU", valid_from: (2023, 1, 1), standard: 160, reduced: &[130, 70, 30] },
+    RateEntry { country: "LU", valid_from: (2024, 1, 1), standard: 170, reduced: &[140, 80, 30] },
+    RateEntry { country: "LV", valid_from: (2021, 7, 1), standard: 210, reduced: &[120, 50] },
+    RateEntry { country: "MT", valid_from: (2021, 7, 1), standard: 180, reduced: &[70, 50] },
+    RateEntry { country: "NL", valid_from: (2021, 7, 1), standard: 210, reduced: &[90] },
+    RateEntry { country: "PL", valid_from: (2021, 7, 1), standard: 230, reduced: &[80, 50] },
+    RateEntry { country: "PT", valid_from: (2021, 7, 1), standard: 230, reduced: &[130, 60] },
+    RateEntry { country: "RO", valid_from: (2021, 7, 1), standard: 190, reduced: &[90, 50] },
+    RateEntry { country: "RO", valid_from: (2025, 8, 1), standard: 210, reduced: &[110] },
+    RateEntry { country: "SE", valid_from: (2021, 7, 1), standard: 250, reduced: &[120, 60] },
+    RateEntry { country: "SI", valid_from: (2021, 7, 1), standard: 220, reduced: &[95, 50] },
+    RateEntry { country: "SK", valid_from: (2021, 7, 1), standard: 200, reduced: &[100] },
+    RateEntry { country: "SK", valid_from: (2025, 1, 1), standard: 230, reduced: &[190, 50] },
+];
+
+/// The rates of a member state on a given day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VatRates {
+    pub country: &'static str,
+    pub valid_from: NaiveDate,
+    pub standard: Decimal,
+    pub reduced: Vec<Decimal>,
+}
+
+impl VatRates {
+    /// Standard rate first, then the reduced rates.
+    pub fn all(&self) -> Vec<Decimal> {
+        std::iter::once(self.standard).chain(self.reduced.iter().copied()).collect()
+    }
+}
+
+/// The rates that applied in `country` on `date`, `None` outside the EU or before the table starts.
+pub fn rates(country: &str, date: NaiveDate) -> Option<VatRates> {
+    RATES.iter()
+        .filter(|entry| entry.country == country)
+        .filter_map(|entry| {
+            let (year, month, day) = entry.valid_from;
+            NaiveDate::from_ymd_opt(year, month, day).map(|valid_from| (valid_from, entry))
+        })
+        .filter(|(valid_from, _)| *valid_from <= date)
+        .max_by_key(|(valid_from, _)| *valid_from)
+        .map(|(valid_from, entry)| VatRates {
+            country: entry.country,
+            valid_from,
+            standard: Decimal::new(entry.standard.into(), 1),
+            reduced: entry.reduced.iter().map(|rate| Decimal::new((*rate).into(), 1)).collect(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).expect("valid date")
+    }
+
+    fn rates_on(country: &str, date: NaiveDate) -> Option<Vec<String>> {
+        rates(country, date).map(|rates| rates.all().iter().map(|rate| rate.normalize().to_string()).collect())
+    }
+
+    #[test]
+    fn rates_change_on_the_day_of_the_new_entry() {
+        assert_eq!(rates_on("EE", date(2023, 12, 31)), Some(vec!["20".to_string(), "9".to_string()]));
+        assert_eq!(rates_on("EE", date(2024, 1, 1)), Some(vec!["22".to_string(), "9".to_string(), "5".to_string()]));
+        assert_eq!(rates_on("SK", date(2024, 12, 31)), Some(vec!["20".to_string(), "10".to_string()]));
+        assert_eq!(rates_on("SK", date(2025, 1, 1)), Some(vec!["23".to_string(), "19".to_string(), "5".to_string()]));
+        assert_eq!(rates("SK", date(2025, 1, 1)).map(|rates| rates.valid_from), Some(date(2025, 1, 1)));
+        assert_eq!(rates_on("FR", date(2024, 6, 1)), Some(vec!["20".to_string(), "10".to_string(), "5.5".to_string(),
+                                                               "2.1".to_string()]));
+    }
+
+    #[test]
+    fn no_rates_before_the_table_or_outside_the_eu() {
+        assert_eq!(rates("DE", date(2021, 6, 30)), None);
+        assert_eq!(rates("DE", date(2021, 7, 1)).map(|rates| rates.standard), Some(Decimal::new(19, 0)));
+        assert_eq!(rates("US", date(2024, 1, 1)), None);
+        assert_eq!(rates("CH", date(2024, 1, 1)), None);
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fs;
+use log::{info, warn};
+use reqwest::Client;
+use serde::Serialize;
+use crate::error::{Error, Result};
+use crate::invoice::invoice::{InvoiceCSV, VoucherCreateRequest};
+use crate::lexoffice::{self, Voucher};
+use crate::report::render_table;
+use crate::settings::Config;
+use crate::state::{InvoiceRecord, InvoiceState, StateStore};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerifyStatus {
+    /// the voucher matches the csv row and has a file
+    Ok,
+    Mismatch,
+    /// lexoffice does not know the voucher id, or no voucher has the invoice number
+    Missing,
+    /// the voucher could not be fetched or the expected voucher could not be built from the csv row
+    Error,
+}
+
+impl VerifyStatus {
+    fn name(self) -> &'static str {
+        match self {
+            VerifyStatus::Ok => "ok",
+            VerifyStatus::Mismatch => "mismatch",
+            VerifyStatus::Missing => "missing",
+            VerifyStatus::Error => "error",
+        }
+    }
+}
+
+/// A field of the voucher that differs from the csv row.
+#[derive(Serialize, Debug, Clone)]
+pub struct Mismatch {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// The result for one uploaded invoice.
+#[derive(Serialize, Debug, Clone)]
+pub struct VerifyEntry {
+    pub invoice_number: String,
+    pub voucher_id: Option<String>,
+    pub status: VerifyStatus,
+    pub mismatches: Vec<Mismatch>,
+    pub message: Option<String>,
+}
+
+impl VerifyEntry {
+    fn new(invoice_number: &str, voucher_id: Option<String>, status: VerifyStatus) -> Self {
+        Self { invoice_number: invoice_number.to_string(), voucher_id, status, mismatches: vec![], message: None }
+    }
+
+    fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+/// Fetches the voucher of every uploaded invoice in the state database and compares it with its csv row.
+/// The config must be non-interactive and have a checked api key, see `Uploader::prepare`.
+pub async fn verify(config: &mut Config, state: &StateStore, invoices: &[InvoiceCSV]) -> Result<Vec<VerifyEntry>> {
+    let rows: HashMap<&str, &InvoiceCSV> = invoices.iter().map(|invoice| (invoice.invoice_number(), invoice)).collect();
+    let records: Vec<_> = state.records()?.into_iter().filter(|record| record.state == InvoiceState::FileUploaded).collect();
+    info!("Verifying {} uploaded invoices of profile {}", records.len(), config.display_name());
+
+    let client = Client::new();
+    let mut entries = vec![];
+    for record in records {
+        let number = record.invoice_number.as_str();
+        let voucher_id = match lookup_voucher_id(&client, config, state, &record).await {
+            Ok(VoucherLookup::Found(voucher_id)) => voucher_id,
+            Ok(VoucherLookup::NotFound) => {
+                entries.push(VerifyEntry::new(number, None, VerifyStatus::Missing)
+                    .with_message("no voucher with this number exists in lexoffice"));
+                continue;
+            }
+            Ok(VoucherLookup::Ambiguous(count)) => {
+                entries.push(VerifyEntry::new(number, None, VerifyStatus::Error)
+                    .with_message(format!("{} vouchers in lexoffice have this number", count)));
+                continue;
+            }
+            Err(e) => {
+                warn!("Could not look up the voucher of invoice {}: {}", number, e);
+                entries.push(VerifyEntry::new(number, None, VerifyStatus::Error).with_message(e.to_string()));
+                continue;
+            }
+        };
+
+        let voucher = match lexoffice::get_voucher(&client, config, &voucher_id).await {
+            Ok(voucher) => voucher,
+            Err(Error::Api { status: 404, .. }) => {
+                entries.push(VerifyEntry::new(number, Some(voucher_id), VerifyStatus::Missing)
+                    .with_message("voucher does not exist in lexoffice"));
+                continue;
+            }
+            Err(e) => {
+                warn!("Could not fetch voucher {} of invoice {}: {}", voucher_id, number, e);
+                entries.push(VerifyEntry::new(number, Some(voucher_id), VerifyStatus::Error).with_message(e.to_string()));
+                continue;
+            }
+        };
+
+        let mut entry = VerifyEntry::new(number, Some(voucher_id), VerifyStatus::Ok);
+        match rows.get(number) {
+            Some(invoice) => match invoice.build_voucher(config) {
+                Ok(expected) => entry.mismatches = compare(&expected, &voucher),
+                Err(e) => {
+                    entry.status = VerifyStatus::Error;
+                    entry.message = Some(format!("cannot rebuild the voucher from the csv row: {}", e));
+                }
+            },
+            None => entry.message = Some("invoice is no longer in the csv, only the file was checked".to_string()),
+        }
+        entry.mismatches.extend(check_file_and_status(&voucher));
+        if !entry.mismatches.is_empty() && entry.status == VerifyStatus::Ok {
+            entry.status = VerifyStatus::Mismatch;
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Outcome of `lookup_voucher_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoucherLookup {
+    Found(String),
+    NotFound,
+    /// several vouchers have the invoice number, none is picked
+    Ambiguous(usize),
+}
+
+/// The recorded voucher id of an uploaded invoice. Invoices imported from a done invoices csv have none, their
+/// voucher is searched by invoice number and a unique match is stored in the state database.
+pub async fn lookup_voucher_id(client: &Client, config: &Config, state: &StateStore, record: &InvoiceRecord)
+                               -> Result<VoucherLookup> {
+    if let Some(voucher_id) = &record.voucher_id {
+        return Ok(VoucherLookup::Found(voucher_id.clone()));
+    }
+    let mut ids = lexoffice::find_voucher_ids(client, config, &record.invoice_number).await?;
+    match ids.len() {
+        0 => Ok(VoucherLookup::NotFound),
+        1 => {
+            let voucher_id = ids.remove(0);
+            info!("Found voucher {} of invoice {} by its number", voucher_id, record.invoice_number);
+            state.record_voucher_id(&record.invoice_number, &voucher_id)?;
+            Ok(VoucherLookup::Found(voucher_id))
+        }
+        count => Ok(VoucherLookup::Ambiguous(count)),
+    }
+}
+
+/// The fields of `voucher` that differ from the voucher that would be created from the csv row today.
+pub fn compare(expected: &VoucherCreateRequest, voucher: &Voucher) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    let mut check = |field: &str, expected: Option<String>, actual: Option<String>| {
+        if expected != actual {
+            mismatches.push(mismatch(field, expected.as_deref().unwrap_or("-"), actual.as_deref().unwrap_or("-")));
+        }
+    };
+
+    check("voucherNumber", Some(expected.voucher_number.clone()), voucher.voucher_number.clone());
+    // lexoffice answers with timestamps in its own timezone, the date part is what was sent
+    check("voucherDate", Some(expected.voucher_date.clone()), date_part(&voucher.voucher_date));
+    check("shippingDate", expected.shipping_date.clone(), date_part(&voucher.shipping_date));
+    if expected.due_date.is_some() {
+        check("dueDate", expected.due_date.clone(), date_part(&voucher.due_date));
+    }
+    check("totalGrossAmount", Some(expected.total_gross_amount.normalize().to_string()),
+          voucher.total_gross_amount.map(|amount| amount.normalize().to_string()));
+    check("totalTaxAmount", Some(expected.total_tax_amount.normalize().to_string()),
+          voucher.total_tax_amount.map(|amount| amount.normalize().to_string()));
+    check("taxType", Some(expected.tax_type.clone()), voucher.tax_type.clone());
+    check("contactId", Some(expected.contact_id.clone()), voucher.contact_id.clone());
+    if expected.remark.is_some() {
+        check("remark", expected.remark.clone(), voucher.remark.clone());
+    }
+
+    if expected.voucher_items.len() != voucher.voucher_items.len() {
+        check("voucherItems", Some(expected.voucher_items.len().to_string()), Some(voucher.voucher_items.len().to_string()));
+    } else {
+        for (index, (expected, actual)) in expected.voucher_items.iter().zip(&voucher.voucher_items).enumerate() {
+            let field = |name: &str| format!("voucherItems[{}].{}", index, name);
+            check(&field("amount"), Some(expected.amount.normalize().to_string()), Some(actual.amount.normalize().to_string()));
+            check(&field("taxAmount"), Some(expected.tax_amount.normalize().to_string()),
+                  Some(actual.tax_amount.normalize().to_string()));
+            check(&field("taxRatePercent"), Some(expected.tax_rate_percent.normalize().to_string()),
+                  Some(actual.tax_rate_percent.normalize().to_string()));
+            check(&field("categoryId"), Some(expected.category_id.clone()), Some(actual.category_id.clone()));
+        }
+    }
+    mismatches
+}
+
+/// Problems of the voucher itself, checked even if the csv row is gone: no attached pdf or voided.
+pub fn check_file_and_status(voucher: &Voucher) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    if voucher.files.is_empty() {
+        mismatches.push(mismatch("files", "attached pdf", "none"));
+    }
+    if voucher.voucher_status.as_deref() == Some("voided") {
+        mismatches.push(mismatch("voucherStatus", "not voided", "voided"));
+    }
+    mismatches
+}
+
+fn date_part(timestamp: &Option<String>) -> Option<String> {
+    timestamp.as_ref().map(|timestamp| timestamp.chars().take(10).collect())
+}
+
+fn mismatch(field: &str, expected: &str, actual: &str) -> Mismatch {
+    Mismatch { field: field.to_string(), expected: expected.to_string(), actual: actual.to_string() }
+}
+
+/// One line per invoice that is not ok, followed by a summary line.
+pub fn render_verify(entries: &[VerifyEntry]) -> String {
+    let mut rows = vec![];
+    for entry in entries.iter().filter(|entry| entry.status != VerifyStatus::Ok) {
+        let mut details: Vec<String> = entry.mismatches.iter()
+            .map(|m| format!("{}: expected {}, got {}", m.field, m.expected, m.actual))
+            .collect();
+        details.extend(entry.message.clone());
+        rows.push(vec![
+            entry.invoice_number.clone(),
+            entry.status.name().to_string(),
+            entry.voucher_id.clone().unwrap_or_default(),
+            details.join("; "),
+        ]);
+    }
+
+    let count = |status| entries.iter().filter(|entry| entry.status == status).count();
+    let mut out = String::new();
+    if !rows.is_empty() {
+        out.push_str(&render_table(&["Invoice", "Status", "Voucher", "Details"], &rows));
+    }
+    out.push_str(&format!("Verified {} invoices: {} ok, {} mismatch, {} missing, {} errors\n",
+                          entries.len(), count(VerifyStatus::Ok), count(VerifyStatus::Mismatch),
+                          count(VerifyStatus::Missing), count(VerifyStatus::Error)));
+    out
+}
+
+pub fn write_json(entries: &[VerifyEntry], path: &str) -> Result<()> {
+    let json = serde_json::to_string_pretty(entries).map_err(|e| Error::json(path, e))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTACT_ID: &str = "6f4b2c1a-0a4e-4c56-9d2e-2b1f3a6c7d8e";
+    const CATEGORY_ID: &str = "8f8664a8-fd86-11e1-a21f-0800200c9a66";
+
+    fn expected() -> VoucherCreateRequest {
+        serde_json::from_value(serde_json::json!({
+            "type": "salesinvoice",
+            "voucherNumber": "AB-1",
+            "voucherDate": "2024-01-15",
+            "shippingDate": "2024-01-10",
+            "dueDate": null,
+            "totalGrossAmount": "119.00",
+            "totalTaxAmount": "19.00",
+            "taxType": "gross",
+            "contactId": CONTACT_ID,
+            "voucherItems": [{"amount": "119.00", "taxAmount": "19.00", "taxRatePercent": "19", "categoryId": CATEGORY_ID}],
+        })).expect("valid voucher request")
+    }
+
+    /// The voucher as lexoffice returns it for `expected`, changed by `edit`.
+    fn voucher(edit: impl FnOnce(&mut serde_json::Value)) -> Voucher {
+        let mut voucher = serde_json::json!({
+            "id": "voucher-1",
+            "version": 2,
+            "voucherStatus": "open",
+            "voucherNumber": "AB-1",
+            "voucherDate": "2024-01-15T00:00:00.000+01:00",
+            "shippingDate": "2024-01-10T00:00:00.000+01:00",
+            "totalGrossAmount": 119,
+            "totalTaxAmount": 19,
+            "taxType": "gross",
+            "contactId": CONTACT_ID,
+            "voucherItems": [{"amount": 119.0, "taxAmount": 19.0, "taxRatePercent": 19.0, "categoryId": CATEGORY_ID}],
+            "files": ["file-1"],
+        });
+        edit(&mut voucher);
+        serde_json::from_value(voucher).expect("valid voucher")
+    }
+
+    fn fields(mismatches: &[Mismatch]) -> Vec<(&str, &str, &str)> {
+        mismatches.iter().map(|m| (m.field.as_str(), m.expected.as_str(), m.actual.as_str())).collect()
+    }
+
+    #[test]
+    fn matching_voucher_has_no_mismatches() {
+        let voucher = voucher(|_| {});
+        assert!(compare(&expected(), &voucher).is_empty());
+        assert!(check_file_and_status(&voucher).is_empty());
+    }
+
+    #[test]
+    fn differing_fields_are_reported() {
+        let voucher = voucher(|voucher| {
+            voucher["totalGrossAmount"] = serde_json::json!(120);
+            voucher["voucherItems"][0]["categoryId"] = serde_json::json!("other-category");
+            voucher["shippingDate"] = serde_json::Value::Null;
+        });
+        assert_eq!(fields(&compare(&expected(), &voucher)), vec![
+            ("shippingDate", "2024-01-10", "-"),
+            ("totalGrossAmount", "119", "120"),
+            ("voucherItems[0].categoryId", CATEGORY_ID, "other-category"),
+        ]);
+    }
+
+    #[test]
+    fn missing_file_and_voided_vouchers_are_reported() {
+        let voucher = voucher(|voucher| {
+            voucher["files"] = serde_json::json!([]);
+            voucher["voucherStatus"] = serde_json::json!("voided");
+        });
+        assert!(compare(&expected(), &voucher).is_empty());
+        assert_eq!(fields(&check_file_and_status(&voucher)), vec![
+            ("files", "attached pdf", "none"),
+            ("voucherStatus", "not voided", "voided"),
+        ]);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::{error, info, warn};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use tokio::sync::mpsc;
+use cli_lexuploader::settings;
+use crate::cli::{Cli, UploadArgs};
+use crate::progress::ProgressMode;
+use crate::INVOICE_CSV;
+
+/// Uploads the pending invoices, then again whenever invoices.csv or a pdf in one of the prefix folders changes.
+/// Invoices whose pdf is missing are held back until it shows up.
+pub async fn watch(cli: &Cli, args: &UploadArgs, debounce: Duration, progress: ProgressMode) {
+    let (tx, mut changes) = mpsc::unbounded_channel();
+    let debouncer = new_debouncer(debounce, move |result: DebounceEventResult| match result {
+        Ok(events) => {
+            // the state database, failures file and log are written next to the csv, only inputs count
+            if events.iter().any(|event| is_input(&event.path)) {
+                let _ = tx.send(());
+            }
+        }
+        Err(e) => warn!("Error watching files: {}", e),
+    });
+    let mut debouncer = match debouncer {
+        Ok(debouncer) => debouncer,
+        Err(e) => {
+            error!("Could not start watching: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut watched = HashSet::new();
+    loop {
+        // prompts during a run can add prefixes, so the folders are refreshed every time
+        watch_inputs(&mut debouncer, &mut watched, cli);
+        if let Some(report) = crate::run_uploads(cli, args, None, progress, true).await {
+            if !report.entries.is_empty() || !report.failed_profiles.is_empty() {
+                print!("{}", report.render_text());
+                crate::write_report(&report, args);
+            }
+        }
+
+        info!("Waiting for changes of {} and the invoice folders", INVOICE_CSV);
+        tokio::select! {
+            change = changes.recv() => if change.is_none() {
+                break;
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Stopped watching");
+                break;
+            }
+        }
+    }
+}
+
+fn is_input(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == INVOICE_CSV)
+        || path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+/// Adds the folder of the csv and the prefix folders of the selected profiles that are not watched yet.
+fn watch_inputs(debouncer: &mut Debouncer<RecommendedWatcher>, watched: &mut HashSet<PathBuf>, cli: &Cli) {
+    let csv_folder = Path::new(INVOICE_CSV).parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut folders = vec![(csv_folder.to_path_buf(), RecursiveMode::NonRecursive)];
+
+    match settings::load_settings().and_then(|settings| crate::select_configs(&settings, cli)) {
+        Ok(configs) => {
+            for config in &configs {
+                // the pdfs are in one subfolder per delivery month
+                folders.extend(config.prefixes.iter().flatten()
+                    .map(|prefix| (PathBuf::from(&prefix.path), RecursiveMode::Recursive)));
+            }
+        }
+        Err(e) => warn!("Could not read the prefix folders to watch: {}", e),
+    }
+
+    for (folder, mode) in folders {
+        if watched.contains(&folder) || !folder.exists() {
+            continue;
+        }
+        match debouncer.watcher().watch(&folder, mode) {
+            Ok(()) => {
+                info!("Watching {}", folder.display());
+                watched.insert(folder);
+            }
+            Err(e) => warn!("Could not watch {}: {}", folder.display(), e),
+        }
+    }
+}